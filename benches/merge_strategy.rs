@@ -0,0 +1,59 @@
+//! Compares the [MergeStrategy](orderbook_challenge::merge::MergeStrategy) implementations on full books from every exchange
+//! at several depths, to find the crossover point run `cargo bench --features test-util`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use orderbook_challenge::{
+    input::{Exchange, Level},
+    merge::{
+        calculate_levels, calculate_levels_bounded_heap, calculate_levels_heap, ExchangeWeighting,
+    },
+    proto::orderbook,
+};
+use std::cmp::Ordering;
+
+/// Levels per side of each exchange.
+const DEPTHS: [usize; 3] = [10, 50, 200];
+
+/// Signature shared by the `calculate_levels` implementations.
+type Calculate = fn(
+    &[Vec<Level>; Exchange::VARIANT_COUNT],
+    fn(&Level, &Level) -> Ordering,
+    usize,
+    &ExchangeWeighting,
+) -> Vec<orderbook::Level>;
+
+/// Returns the asks of every exchange, `depth` levels each, interleaved around the same price.
+fn books(depth: usize) -> [Vec<Level>; Exchange::VARIANT_COUNT] {
+    let mut books: [Vec<Level>; Exchange::VARIANT_COUNT] = Default::default();
+    for (exchange, book) in books.iter_mut().enumerate() {
+        let offset = exchange as f64 * 0.001;
+        *book = (0..depth)
+            .map(|i| {
+                Level::try_from_f64_pair(2. + offset + 0.01 * i as f64, 1. + i as f64).unwrap()
+            })
+            .collect();
+    }
+    books
+}
+
+fn bench_calculate_levels(c: &mut Criterion) {
+    let weighting = ExchangeWeighting::default();
+    for &depth in DEPTHS.iter() {
+        let mut group = c.benchmark_group(format!("calculate_levels/depth={}", depth));
+        let books = books(depth);
+        let size = depth * Exchange::VARIANT_COUNT;
+        let strategies: Vec<(&str, Calculate)> = vec![
+            ("linear", calculate_levels),
+            ("heap", calculate_levels_heap),
+            ("bounded_heap", calculate_levels_bounded_heap),
+        ];
+        for (name, calculate) in strategies {
+            group.bench_function(BenchmarkId::from_parameter(name), |b| {
+                b.iter(|| calculate(&books, Level::cmp_ask, size, &weighting))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_calculate_levels);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+//! Compares polling every source from a single task, like the server does, against spawning a task per source.
+//!
+//! The sources replay [SyntheticMarket] updates without waiting for them to be due and without parsing,
+//! so this measures the overhead of each approach rather than the exchange feeds.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures_util::stream::select_all;
+use orderbook_challenge::{
+    input::{Exchange, InputUpdate, Level},
+    synthetic::SyntheticMarket,
+    CHANNEL_SIZE, TOP_LEVELS,
+};
+use std::convert::TryFrom;
+use tokio::{runtime::Runtime, spawn, sync::mpsc};
+use tokio_stream::StreamExt;
+
+/// Number of updates sent by each source per iteration.
+const UPDATES_PER_SOURCE: usize = 1_000;
+
+/// Returns [UPDATES_PER_SOURCE] updates from `exchange`.
+fn updates(exchange: Exchange) -> Vec<InputUpdate> {
+    let side = |start: f64, step: f64| {
+        (0..TOP_LEVELS)
+            .map(|i| Level::try_from_f64_pair(start + step * i as f64, 1. + i as f64).unwrap())
+            .collect()
+    };
+    let mut market = SyntheticMarket::new(exchange as u64).with_exchange(
+        InputUpdate::new(exchange, side(2., 0.01), side(1.99, -0.001)),
+        10.,
+    );
+    (0..UPDATES_PER_SOURCE)
+        .map(|_| market.next_update().unwrap().1)
+        .collect()
+}
+
+/// Forwards the updates of every source from a single task, returns the number of updates received.
+async fn single_task(sources: Vec<Vec<InputUpdate>>) -> usize {
+    let (tx, mut rx) = mpsc::channel(CHANNEL_SIZE);
+    let mut sources = select_all(sources.into_iter().map(tokio_stream::iter));
+    spawn(async move {
+        while let Some(update) = sources.next().await {
+            tx.send(update).await.unwrap();
+        }
+    });
+    let mut received = 0;
+    while rx.recv().await.is_some() {
+        received += 1;
+    }
+    received
+}
+
+/// Forwards the updates of each source from its own task, returns the number of updates received.
+async fn task_per_source(sources: Vec<Vec<InputUpdate>>) -> usize {
+    let (tx, mut rx) = mpsc::channel(CHANNEL_SIZE);
+    for source in sources {
+        let tx = tx.clone();
+        spawn(async move {
+            for update in source {
+                tx.send(update).await.unwrap();
+            }
+        });
+    }
+    drop(tx);
+    let mut received = 0;
+    while rx.recv().await.is_some() {
+        received += 1;
+    }
+    received
+}
+
+fn bench_source_polling(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("source_polling");
+    for sources in vec![2, Exchange::VARIANT_COUNT] {
+        let updates = (0..sources as u8)
+            .map(|exchange| updates(Exchange::try_from(exchange).unwrap()))
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("single_task", sources),
+            &updates,
+            |b, updates| b.iter(|| runtime.block_on(single_task(updates.clone()))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("task_per_source", sources),
+            &updates,
+            |b, updates| b.iter(|| runtime.block_on(task_per_source(updates.clone()))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_source_polling);
+criterion_main!(benches);
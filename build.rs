@@ -1,4 +1,8 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/orderbook.proto")?;
+    tonic_build::configure()
+        // Used to write summaries as JSON, see `examples/stdout.rs`.
+        .type_attribute(".orderbook.Summary", "#[derive(serde::Serialize)]")
+        .type_attribute(".orderbook.Level", "#[derive(serde::Serialize)]")
+        .compile(&["proto/orderbook.proto"], &["proto"])?;
     Ok(())
 }
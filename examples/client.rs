@@ -3,19 +3,76 @@ use orderbook_challenge::*;
 use proto::orderbook;
 use tonic::transport::Endpoint;
 
+/// Returns the value of the `--exchange` flag, if provided.
+fn exchange_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--exchange" {
+            return Some(args.next().expect(
+                "Please provide an exchange name after --exchange, for example: --exchange binance",
+            ));
+        }
+        if let Some(exchange) = arg.strip_prefix("--exchange=") {
+            return Some(exchange.to_string());
+        }
+    }
+    None
+}
+
+/// Returns the value of the `--depth` flag, 0 if not provided.
+fn depth_arg() -> u32 {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let depth = if arg == "--depth" {
+            args.next()
+        } else {
+            arg.strip_prefix("--depth=").map(str::to_string)
+        };
+        if let Some(depth) = depth {
+            return depth
+                .parse()
+                .expect("Please provide a number of levels after --depth, for example: --depth 5");
+        }
+    }
+    0
+}
+
+/// Removes the levels of `summary` which don't come from `exchange`.
+fn filter_exchange(mut summary: orderbook::Summary, exchange: &str) -> orderbook::Summary {
+    summary.asks.retain(|level| level.exchange == exchange);
+    summary.bids.retain(|level| level.exchange == exchange);
+    summary
+        .per_exchange_spread
+        .retain(|name, _| name == exchange);
+    summary
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let exchange = exchange_arg();
+    let depth = depth_arg();
+
     let channel = Endpoint::from_static("http://0.0.0.0:5005")
         .connect()
         .await?;
 
     let mut orderbook_client = OrderbookAggregatorClient::new(channel.clone());
 
-    let request = tonic::Request::new(orderbook::Empty {});
+    let request = tonic::Request::new(orderbook::BookSummaryRequest { depth });
 
     let mut response = orderbook_client.book_summary(request).await?.into_inner();
 
     while let Ok(Some(summary)) = response.message().await {
+        let summary = match &exchange {
+            Some(exchange) => {
+                let filtered = filter_exchange(summary, exchange);
+                if filtered.asks.is_empty() && filtered.bids.is_empty() {
+                    eprintln!("Warning: no levels from exchange {:?} in summary", exchange);
+                }
+                filtered
+            }
+            None => summary,
+        };
         println!("{:#?}", summary)
     }
 
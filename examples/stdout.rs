@@ -0,0 +1,51 @@
+use input::*;
+use orderbook_challenge::*;
+use sources::{BackoffConfig, SourceConfig};
+use std::io::Write;
+use tokio::{select, spawn, sync::mpsc};
+use tokio_stream::StreamExt;
+
+/// Writes every merged summary to stdout as [JSON Lines](https://jsonlines.org/), for example to pipe it into `jq`.
+#[tokio::main]
+async fn main() {
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+
+    let pair = std::env::var("PAIR").expect(
+        "Please provide a trading pair in the PAIR environment variable for example: PAIR=ethbtc",
+    );
+    let pair_c = pair.clone();
+    let backoff_config = BackoffConfig::default();
+
+    // Spawn sources task, a single task polls both exchanges.
+    spawn(async move {
+        let bitstamp =
+            sources::bitstamp::get_stream(pair, backoff_config.factory(), SourceConfig::default());
+        let binance =
+            sources::binance::get_stream(pair_c, backoff_config.factory(), SourceConfig::default());
+        tokio::pin!(bitstamp);
+        tokio::pin!(binance);
+        loop {
+            let item = select! {
+                Some(item) = bitstamp.next() => item,
+                Some(item) = binance.next() => item,
+                else => break,
+            };
+            tx.send(item).await.unwrap();
+        }
+    });
+
+    let stream = merge::merge(rx);
+    tokio::pin!(stream);
+    let stdout = std::io::stdout();
+    while let Some(summary) = stream.next().await {
+        let mut stdout = stdout.lock();
+        // Stop quietly if the reader goes away, for example `head`.
+        if stdout
+            .write_all(summary.to_json_line().as_bytes())
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
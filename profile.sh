@@ -1 +1 @@
-PAIR=ethbtc cargo flamegraph --example server
+cargo flamegraph --features cli --bin server -- --pair ethbtc
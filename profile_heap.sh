@@ -1 +1 @@
-PAIR=ethbtc heaptrack target/release/examples/server
+heaptrack target/release/server --pair ethbtc
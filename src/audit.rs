@@ -0,0 +1,131 @@
+use crate::{merge::MergeState, proto::orderbook, transform::Pipeline};
+use std::time::{Duration, Instant};
+use tokio::sync::watch::Receiver;
+
+/// Periodically compares the latest served [orderbook::Summary] with a reference recomputed from the merged state.
+///
+/// Catches bugs in the transforms or anything else that mutates summaries between the merge and the server,
+/// see [merge_with_audit](crate::merge::merge_with_audit).
+pub struct Audit {
+    served: Receiver<Option<orderbook::Summary>>,
+    reference: Pipeline,
+    interval: Duration,
+    next: Instant,
+}
+
+impl Audit {
+    /// Returns a new [Audit] which every `interval` compares the latest summary in `served` with the merged state
+    /// after applying `reference`, which should contain the transforms the served summaries are expected to go through.
+    pub fn new(
+        served: Receiver<Option<orderbook::Summary>>,
+        reference: Pipeline,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            served,
+            reference,
+            interval,
+            next: Instant::now() + interval,
+        }
+    }
+
+    /// Runs the check if the interval has elapsed since the previous one, returns true if there was a mismatch.
+    pub(crate) fn check(&mut self, state: &MergeState) -> bool {
+        self.check_at(Instant::now(), state)
+    }
+
+    /// Runs the check if the interval has elapsed at `now`, returns true if there was a mismatch.
+    ///
+    /// Mismatches are logged, nothing is checked until a summary has been served.
+    pub(crate) fn check_at(&mut self, now: Instant, state: &MergeState) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next = now + self.interval;
+
+        let reference = self.reference.apply(state.emitted_summary());
+        // The borrow blocks the sender, so the reference is computed before taking it.
+        let served = self.served.borrow();
+        match served.as_ref() {
+            Some(served) if *served != reference => {
+                eprintln!(
+                    "Audit mismatch, served {:?} but the merged state produces {:?}",
+                    served, reference
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        arrayvec,
+        input::{Exchange, InputUpdate},
+        merge::SPREAD_HISTORY_WINDOW,
+        transform::DepthCap,
+    };
+    use tokio::sync::watch;
+
+    #[test]
+    fn test_audit() {
+        let mut state = MergeState::new();
+        state.update(InputUpdate::new(
+            Exchange::Binance,
+            arrayvec![lvl!(2., 1.), lvl!(3., 1.)],
+            arrayvec![lvl!(1., 1.), lvl!(0.5, 1.)],
+        ));
+        let pipeline = || Pipeline::new().with(DepthCap(2));
+        // Drops the best ask.
+        let buggy = pipeline().with(|mut summary: orderbook::Summary| {
+            summary.asks.remove(0);
+            summary
+        });
+
+        let (tx, rx) = watch::channel(None);
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let mut audit = Audit::new(rx, pipeline(), interval);
+
+        // Nothing served yet.
+        assert!(!audit.check_at(start + interval, &state));
+
+        tx.send(Some(pipeline().apply(state.summary()))).unwrap();
+        assert!(!audit.check_at(start + 2 * interval, &state));
+
+        tx.send(Some(buggy.apply(state.summary()))).unwrap();
+        // The interval hasn't elapsed since the previous check.
+        assert!(!audit.check_at(start + 2 * interval, &state));
+        assert!(audit.check_at(start + 3 * interval, &state));
+    }
+
+    #[test]
+    fn test_audit_changes_only() {
+        let mut state = MergeState::new()
+            .with_changes_only(true)
+            .with_spread_history(SPREAD_HISTORY_WINDOW);
+        let update = || {
+            InputUpdate::new(
+                Exchange::Binance,
+                arrayvec![lvl!(2., 1.)],
+                arrayvec![lvl!(1., 1.)],
+            )
+        };
+        state.update(update());
+        let (tx, rx) = watch::channel(None);
+        tx.send(state.summary_if_changed()).unwrap();
+
+        // Same book, the spread history grows but nothing is emitted.
+        state.update(update());
+        assert!(state.summary_if_changed().is_none());
+        assert_ne!(Some(state.summary()), *rx.borrow());
+
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let mut audit = Audit::new(rx, Pipeline::new(), interval);
+        assert!(!audit.check_at(start + interval, &state));
+    }
+}
@@ -0,0 +1,156 @@
+use clap::Parser;
+use futures_util::stream::select_all;
+use input::*;
+use orderbook_challenge::*;
+use proto::orderbook::orderbook_aggregator_server::OrderbookAggregatorServer;
+use serve::Aggregator;
+use sources::BackoffConfig;
+use std::{
+    convert::TryFrom,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{
+    spawn,
+    sync::{mpsc, watch},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::transport::Server;
+
+/// Time between checks of the served summaries.
+const AUDIT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Parser, Debug)]
+#[clap(about = "Streams the merged order book of a trading pair over gRPC")]
+/// Command line arguments of the server, every flag can also be set with its environment variable.
+struct Args {
+    /// Trading pair to merge, for example ethbtc.
+    #[clap(long, env = "PAIR")]
+    pair: String,
+    /// Port to serve the gRPC endpoint on, on every interface.
+    #[clap(long, env = "PORT", default_value_t = 5005)]
+    port: u16,
+    /// Comma separated exchanges to stream from.
+    #[clap(
+        long,
+        env = "EXCHANGES",
+        value_delimiter = ',',
+        parse(try_from_str = parse_exchange),
+        default_value = "bitstamp,binance"
+    )]
+    exchanges: Vec<Exchange>,
+    /// Number of levels per side merged from each exchange, at least 1. Above the default only exchanges
+    /// which send diffs, like coinbase, provide the deeper levels.
+    #[clap(long, env = "TOP_LEVELS", default_value_t = TOP_LEVELS)]
+    top_levels: usize,
+    /// Number of updates buffered between the sources and the merger.
+    #[clap(long, env = "CHANNEL_SIZE", default_value_t = CHANNEL_SIZE, parse(try_from_str = parse_channel_size))]
+    channel_size: usize,
+    /// Every summary includes the spreads of this window, the history isn't kept if it's 0.
+    #[clap(long, env = "SPREAD_HISTORY_SECONDS", default_value_t = 0)]
+    spread_history_seconds: u64,
+}
+
+/// Parses an [Exchange] name, the error lists the valid names.
+fn parse_exchange(name: &str) -> Result<Exchange, String> {
+    name.parse().map_err(|err: UnknownExchange| {
+        let names = (0..Exchange::VARIANT_COUNT as u8)
+            .filter_map(|exchange| Exchange::try_from(exchange).ok())
+            .map(|exchange| exchange.to_string())
+            .collect::<Vec<_>>();
+        format!("{}, expected one of {}", err, names.join(", "))
+    })
+}
+
+/// Parses a channel size, which must not be 0.
+fn parse_channel_size(size: &str) -> Result<usize, String> {
+    match size.parse() {
+        Ok(0) => Err("the channel size must be at least 1".to_string()),
+        Ok(size) => Ok(size),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+/// Spawns the sources task, a single task polls every source, and returns a [Stream] of their items
+/// buffered in a channel of `channel_size`.
+fn spawn_sources<T: Send + 'static>(
+    sources: Vec<Pin<Box<dyn Stream<Item = T> + Send>>>,
+    channel_size: usize,
+) -> ReceiverStream<T> {
+    let (tx, rx) = mpsc::channel(channel_size);
+    spawn(async move {
+        let mut sources = select_all(sources);
+        while let Some(item) = sources.next().await {
+            tx.send(item).await.unwrap();
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let backoff_config = BackoffConfig::default();
+
+    let (summaries_tx, summaries_rx) = watch::channel(None);
+    // Transforms applied to every summary before serving it.
+    let pipeline = transform::Pipeline::new();
+    // Periodically check that the served summaries match the merged state,
+    // the reference must contain the same transforms as `pipeline`.
+    let audit = audit::Audit::new(
+        summaries_rx.clone(),
+        transform::Pipeline::new(),
+        AUDIT_INTERVAL,
+    );
+    // Exchanges left out of the summaries, updated by the BlockExchange rpc.
+    let filter = Arc::new(RwLock::new(merge::ExchangeFilter::default()));
+    let state = merge::MergeState::new()
+        .with_exchange_filter(filter.clone())
+        .with_top_levels(args.top_levels)
+        .with_spread_history(Duration::from_secs(args.spread_history_seconds));
+    // Updates carry at most TOP_LEVELS levels, deeper books are merged from the diffs of the exchanges which send them.
+    let summaries: Pin<Box<dyn Stream<Item = proto::orderbook::Summary> + Send>> =
+        if args.top_levels > TOP_LEVELS {
+            let sources = args
+                .exchanges
+                .iter()
+                .map(|exchange| sources::diff_source(*exchange, args.pair.clone(), &backoff_config))
+                .collect();
+            let inputs = spawn_sources(sources, args.channel_size);
+            Box::pin(merge::merge_diffs_with_state(
+                inputs,
+                state,
+                |_| {},
+                Some(audit),
+            ))
+        } else {
+            let sources = args
+                .exchanges
+                .iter()
+                .map(|exchange| sources::source(*exchange, args.pair.clone(), &backoff_config))
+                .collect();
+            let inputs = spawn_sources(sources, args.channel_size);
+            Box::pin(merge::merge_with_state(inputs, state, |_| {}, Some(audit)))
+        };
+    // Spawn merge task.
+    spawn(async move {
+        let stream = summaries.map(move |summary| pipeline.apply(summary));
+        tokio::pin!(stream);
+        while let Some(item) = stream.next().await {
+            summaries_tx.send(Some(item)).expect("Watch channel broke!");
+        }
+    });
+
+    // Start server.
+    Server::builder()
+        .add_service(OrderbookAggregatorServer::new(
+            // Validate summaries in debug builds.
+            Aggregator::new(summaries_rx)
+                .with_validation(cfg!(debug_assertions))
+                .with_exchange_filter(filter),
+        ))
+        .serve(([0, 0, 0, 0], args.port).into())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,127 @@
+use super::{FinitePositiveF64, Level};
+use serde::{
+    de::{self, IgnoredAny, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{convert::TryFrom, fmt};
+
+/// Wrapper around [Level] to deserialize it from a `[price, amount, ...]` array, where price and amount
+/// can be encoded either as numbers or as strings, the rest of the items are ignored.
+pub struct DeserializeLevelTuple(Level);
+
+impl Into<Level> for DeserializeLevelTuple {
+    fn into(self) -> Level {
+        self.0
+    }
+}
+
+/// f64 which can be deserialized from either a number or a string.
+struct NumberOrString(f64);
+
+impl<'de> Deserialize<'de> for NumberOrString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct NumberVisitor;
+        impl<'de> Visitor<'de> for NumberVisitor {
+            type Value = NumberOrString;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("number or numeric string")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                Ok(NumberOrString(value))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(NumberOrString(value as f64))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(NumberOrString(value as f64))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                fast_float::parse(value)
+                    .map(NumberOrString)
+                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for DeserializeLevelTuple {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SeqVisitor;
+        impl<'de> Visitor<'de> for SeqVisitor {
+            type Value = DeserializeLevelTuple;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("DeserializeLevelTuple")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let mut next = |name: &'static str| -> Result<FinitePositiveF64, V::Error> {
+                    let NumberOrString(value) = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::custom(format!("Missing level {}", name)))?;
+                    FinitePositiveF64::try_from(value).map_err(|_| {
+                        de::Error::invalid_value(de::Unexpected::Float(value), &"FinitePositiveF64")
+                    })
+                };
+                let price = next("price")?;
+                let amount = next("amount")?;
+                // All items must be consumed but we only care about the first 2.
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(DeserializeLevelTuple(Level { price, amount }))
+            }
+        }
+        deserializer.deserialize_seq(SeqVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn deserialize(s: &str) -> Option<Level> {
+        simd_json::from_str::<DeserializeLevelTuple>(&mut s.to_string())
+            .ok()
+            .map(Into::into)
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert_eq!(deserialize("[1.5,2]"), Some(lvl!(1.5, 2.)));
+        assert_eq!(deserialize("[0,0.25]"), Some(lvl!(0., 0.25)));
+        // Extra items like timestamps are ignored.
+        assert_eq!(deserialize("[1.5,2,1612345678.123]"), Some(lvl!(1.5, 2.)));
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(deserialize(r#"["1.5","2"]"#), Some(lvl!(1.5, 2.)));
+        assert_eq!(deserialize(r#"["1.5",2,"r"]"#), Some(lvl!(1.5, 2.)));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(deserialize("[]"), None);
+        assert_eq!(deserialize("[1.5]"), None);
+        assert_eq!(deserialize("[-1.5,2]"), None);
+        assert_eq!(deserialize(r#"["1.5","-2"]"#), None);
+        assert_eq!(deserialize(r#"["blah",2]"#), None);
+        assert_eq!(deserialize(r#"["1.5",null]"#), None);
+        assert_eq!(deserialize(r#"{"price":1.5,"amount":2}"#), None);
+        assert_eq!(deserialize("1.5"), None);
+    }
+}
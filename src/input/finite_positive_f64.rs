@@ -3,26 +3,114 @@ use parse_display::Display;
 use quickcheck::{Arbitrary, Gen};
 use serde::{
     de::{self, Visitor},
-    Deserialize, Deserializer,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
     cmp::Ordering,
     convert::{TryFrom, TryInto},
     fmt,
+    hash::{Hash, Hasher},
+    iter::Sum,
 };
 
 #[derive(Debug, Clone, Copy, Display, PartialEq, PartialOrd)]
 /// Contains an f64 which [is positive](f64::is_sign_positive) and [finite](f64::is_finite).
 pub struct FinitePositiveF64(f64);
 
+impl FinitePositiveF64 {
+    /// Largest [FinitePositiveF64], equal to [f64::MAX].
+    pub const MAX: Self = Self(f64::MAX);
+
+    /// Returns the nearest integer, see [f64::round].
+    ///
+    /// The result of rounding a finite positive number is always finite and positive.
+    pub fn round(self) -> Self {
+        Self(self.0.round())
+    }
+
+    /// Returns the largest integer less than or equal to `self`, see [f64::floor].
+    pub fn floor(self) -> Self {
+        Self(self.0.floor())
+    }
+
+    /// Returns the smallest integer greater than or equal to `self`, see [f64::ceil].
+    pub fn ceil(self) -> Self {
+        Self(self.0.ceil())
+    }
+
+    /// Raises `self` to the integer power `n`, see [f64::powi].
+    ///
+    /// Returns [None] if the result is not finite or is 0, for example a large `n`, 0 to a negative `n`
+    /// or a result which underflows.
+    pub fn powi(self, n: i32) -> Option<Self> {
+        self.0.powi(n).try_into().ok().filter(|v: &Self| v.0 > 0.)
+    }
+
+    /// Raises `self` to the power `e`, see [f64::powf].
+    ///
+    /// Returns [None] if the result is not finite or is 0, for example a [NAN](f64::NAN) `e`
+    /// or a result which underflows.
+    pub fn powf(self, e: f64) -> Option<Self> {
+        self.0.powf(e).try_into().ok().filter(|v: &Self| v.0 > 0.)
+    }
+
+    /// Returns 1 for positive numbers and 0 for 0, a [FinitePositiveF64] is never negative.
+    ///
+    /// Unlike [f64::signum], which returns 1 for 0.
+    pub fn signum(self) -> f64 {
+        if self.0 == 0. {
+            0.
+        } else {
+            1.
+        }
+    }
+
+    /// Divides `self` by `rhs`.
+    ///
+    /// Returns [None] if `rhs` is 0 or the result is not finite, for example dividing by a tiny amount.
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.0 == 0. {
+            return None;
+        }
+        (self.0 / rhs.0).try_into().ok()
+    }
+}
+
 impl Into<f64> for FinitePositiveF64 {
     fn into(self) -> f64 {
         self.0
     }
 }
 
+impl Sum for FinitePositiveF64 {
+    /// Saturates to [FinitePositiveF64::MAX] if the sum overflows.
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        // Folding from 0 since the empty f64 sum is -0.
+        let sum = iter.fold(0., |sum, f| sum + f.0);
+        if sum.is_finite() {
+            Self(sum)
+        } else {
+            Self::MAX
+        }
+    }
+}
+
+impl<'a> Sum<&'a FinitePositiveF64> for FinitePositiveF64 {
+    /// Saturates to [FinitePositiveF64::MAX] if the sum overflows.
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
 impl Eq for FinitePositiveF64 {}
 
+/// Hashes the bits of the value, consistent with [Eq] since NaN and -0 are not valid values.
+impl Hash for FinitePositiveF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
 #[allow(clippy::derive_ord_xor_partial_ord)]
 impl Ord for FinitePositiveF64 {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -74,6 +162,13 @@ impl<'de> Deserialize<'de> for FinitePositiveF64 {
     }
 }
 
+impl Serialize for FinitePositiveF64 {
+    /// Serializes as a number, unlike the exchanges which send strings.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
 #[cfg(test)]
 /// Returns `n.abs()` or 0 if `n` is not finite.
 fn clean_f64(n: f64) -> f64 {
@@ -97,6 +192,7 @@ impl Arbitrary for FinitePositiveF64 {
 mod test {
     use super::*;
     use quickcheck_macros::quickcheck;
+    use std::collections::hash_map::DefaultHasher;
     #[test]
     fn test_try_from() {
         assert_eq!(3.0.try_into(), Ok(FinitePositiveF64(3.)));
@@ -146,6 +242,124 @@ mod test {
         assert!(simd_json::from_str::<FinitePositiveF64>(&mut r#""  1.4  ""#.to_string()).is_err(),);
     }
 
+    #[test]
+    fn test_sum() {
+        let values = [FinitePositiveF64(1.), FinitePositiveF64(2.5)];
+        assert_eq!(
+            values.iter().sum::<FinitePositiveF64>(),
+            FinitePositiveF64(3.5)
+        );
+        assert_eq!(
+            values.iter().copied().sum::<FinitePositiveF64>(),
+            FinitePositiveF64(3.5)
+        );
+        let empty = std::iter::empty::<FinitePositiveF64>().sum::<FinitePositiveF64>();
+        assert_eq!(empty, FinitePositiveF64(0.));
+        assert!(empty.0.is_sign_positive());
+        let hash = |value: FinitePositiveF64| {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(empty), hash(FinitePositiveF64(0.)));
+
+        let large = vec![FinitePositiveF64(2f64.powi(1020)); 1000];
+        assert_eq!(
+            large.iter().sum::<FinitePositiveF64>(),
+            FinitePositiveF64::MAX
+        );
+        // Powers of 2 are exact.
+        assert_eq!(
+            large[..4].iter().sum::<FinitePositiveF64>(),
+            FinitePositiveF64(2f64.powi(1022))
+        );
+    }
+
+    #[test]
+    fn test_pow() {
+        let n = FinitePositiveF64(4.);
+        assert_eq!(n.powi(0), Some(FinitePositiveF64(1.)));
+        assert_eq!(n.powi(1), Some(n));
+        assert_eq!(n.powi(-1), Some(FinitePositiveF64(0.25)));
+        assert_eq!(n.powi(2), Some(FinitePositiveF64(16.)));
+        assert_eq!(FinitePositiveF64(0.).powi(-1), None);
+        assert_eq!(FinitePositiveF64(1e-300).powi(-2), None);
+        assert_eq!(FinitePositiveF64::MAX.powi(2), None);
+        assert_eq!(FinitePositiveF64(1e-300).powi(2), None);
+        assert_eq!(FinitePositiveF64(0.).powi(2), None);
+
+        assert_eq!(n.powf(0.), Some(FinitePositiveF64(1.)));
+        assert_eq!(n.powf(1.), Some(n));
+        assert_eq!(n.powf(-1.), Some(FinitePositiveF64(0.25)));
+        assert_eq!(n.powf(0.5), Some(FinitePositiveF64(2.)));
+        assert_eq!(n.powf(f64::NAN), None);
+        assert_eq!(n.powf(f64::INFINITY), None);
+        assert_eq!(FinitePositiveF64(0.).powf(-0.5), None);
+        assert_eq!(FinitePositiveF64(1e-300).powf(2.), None);
+    }
+
+    #[test]
+    fn test_checked_div() {
+        let n = FinitePositiveF64(3.);
+        assert_eq!(
+            n.checked_div(FinitePositiveF64(2.)),
+            Some(FinitePositiveF64(1.5))
+        );
+        assert_eq!(
+            FinitePositiveF64(0.).checked_div(n),
+            Some(FinitePositiveF64(0.))
+        );
+        assert_eq!(n.checked_div(FinitePositiveF64(0.)), None);
+        assert_eq!(
+            FinitePositiveF64(0.).checked_div(FinitePositiveF64(0.)),
+            None
+        );
+        assert_eq!(
+            FinitePositiveF64::MAX.checked_div(FinitePositiveF64(1e-300)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(FinitePositiveF64(1.4).round(), FinitePositiveF64(1.));
+        assert_eq!(FinitePositiveF64(1.5).round(), FinitePositiveF64(2.));
+        assert_eq!(FinitePositiveF64(0.4).round(), FinitePositiveF64(0.));
+
+        assert_eq!(FinitePositiveF64(1.9).floor(), FinitePositiveF64(1.));
+        assert_eq!(FinitePositiveF64(0.9).floor(), FinitePositiveF64(0.));
+
+        assert_eq!(FinitePositiveF64(1.1).ceil(), FinitePositiveF64(2.));
+        assert_eq!(FinitePositiveF64(0.1).ceil(), FinitePositiveF64(1.));
+
+        assert_eq!(FinitePositiveF64(3.).round(), FinitePositiveF64(3.));
+        assert_eq!(FinitePositiveF64::MAX.ceil(), FinitePositiveF64::MAX);
+    }
+
+    #[quickcheck]
+    fn test_rounding_invariants(floats: Vec<FinitePositiveF64>) {
+        for float in floats {
+            for rounded in &[float.round(), float.floor(), float.ceil()] {
+                assert!(rounded.0.is_finite());
+                assert!(rounded.0.is_sign_positive());
+            }
+        }
+    }
+
+    #[test]
+    fn test_signum() {
+        assert_eq!(FinitePositiveF64(0.).signum(), 0.);
+        assert_eq!(FinitePositiveF64(1e-300).signum(), 1.);
+        assert_eq!(FinitePositiveF64::MAX.signum(), 1.);
+    }
+
+    #[quickcheck]
+    fn test_signum_non_negative(floats: Vec<FinitePositiveF64>) {
+        for float in floats {
+            assert!(float.signum() >= 0.);
+        }
+    }
+
     #[test]
     fn test_clean() {
         assert_eq!(clean_f64(0.), 0.);
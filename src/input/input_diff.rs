@@ -0,0 +1,263 @@
+use super::{Exchange, FinitePositiveF64, InputUpdate, Level, LevelMap};
+use crate::TOP_LEVELS;
+use arrayvec::ArrayVec;
+use std::convert::TryInto;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents the levels which were added, changed or removed in the book of `exchange`, removed levels have an amount of 0.
+///
+/// Snapshots contain the whole book and replace it, the diffs of an exchange start with a snapshot.
+/// Unlike [InputUpdate], the levels are in no particular order and the book isn't limited to [TOP_LEVELS].
+pub struct InputDiff {
+    exchange: Exchange,
+    snapshot: bool,
+    asks: Vec<Level>,
+    bids: Vec<Level>,
+}
+
+impl InputDiff {
+    /// Returns a new [InputDiff] with the levels which changed since the previous diff.
+    pub fn new(exchange: Exchange, asks: Vec<Level>, bids: Vec<Level>) -> Self {
+        Self {
+            exchange,
+            snapshot: false,
+            asks,
+            bids,
+        }
+    }
+
+    /// Returns a new snapshot [InputDiff] with every level of the book.
+    pub fn snapshot(exchange: Exchange, asks: Vec<Level>, bids: Vec<Level>) -> Self {
+        Self {
+            snapshot: true,
+            ..Self::new(exchange, asks, bids)
+        }
+    }
+
+    /// Appends `asks` and `bids` to the levels of the diff, they are applied after the levels already in it.
+    pub fn extend(&mut self, asks: Vec<Level>, bids: Vec<Level>) {
+        self.asks.extend(asks);
+        self.bids.extend(bids);
+    }
+
+    /// Returns the [Exchange] the diff comes from.
+    pub fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+
+    /// Returns true if the diff contains the whole book.
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    /// Returns the asks which changed.
+    pub fn asks(&self) -> &[Level] {
+        &self.asks
+    }
+
+    /// Returns the bids which changed.
+    pub fn bids(&self) -> &[Level] {
+        &self.bids
+    }
+}
+
+impl From<InputUpdate> for InputDiff {
+    /// Returns a snapshot with the levels of the update.
+    fn from(update: InputUpdate) -> Self {
+        let (exchange, asks, bids) = update.take();
+        Self::snapshot(exchange, asks.to_vec(), bids.to_vec())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Book of an [Exchange] maintained by applying [InputDiffs](InputDiff).
+pub struct DiffBook {
+    asks: LevelMap<FinitePositiveF64>,
+    bids: LevelMap<FinitePositiveF64>,
+    has_snapshot: bool,
+}
+
+impl DiffBook {
+    /// Returns a new empty [DiffBook].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `diff` to the book, returns false if it was discarded because it's not a snapshot
+    /// and no snapshot was applied since the book was created or [cleared](Self::clear).
+    pub fn apply(&mut self, diff: &InputDiff) -> bool {
+        if diff.snapshot {
+            self.clear();
+            self.has_snapshot = true;
+        } else if !self.has_snapshot {
+            return false;
+        }
+        apply_levels(&mut self.asks, &diff.asks);
+        apply_levels(&mut self.bids, &diff.bids);
+        true
+    }
+
+    /// Removes every level, the next diff must be a snapshot.
+    pub fn clear(&mut self) {
+        self.asks = LevelMap::new();
+        self.bids = LevelMap::new();
+        self.has_snapshot = false;
+    }
+
+    /// Returns every ask, best first, unlike [top_asks](Self::top_asks) not limited to [TOP_LEVELS].
+    pub fn asks(&self) -> impl Iterator<Item = Level> + '_ {
+        self.asks.iter().map(|(price, amount)| Level {
+            price,
+            amount: *amount,
+        })
+    }
+
+    /// Returns every bid, best first, unlike [top_bids](Self::top_bids) not limited to [TOP_LEVELS].
+    pub fn bids(&self) -> impl Iterator<Item = Level> + '_ {
+        self.bids.iter().rev().map(|(price, amount)| Level {
+            price,
+            amount: *amount,
+        })
+    }
+
+    /// Returns the best `n` asks, `n` is clamped to [TOP_LEVELS].
+    pub fn top_asks(&self, n: usize) -> ArrayVec<[Level; TOP_LEVELS]> {
+        self.asks().take(n.min(TOP_LEVELS)).collect()
+    }
+
+    /// Returns the best `n` bids, `n` is clamped to [TOP_LEVELS].
+    pub fn top_bids(&self, n: usize) -> ArrayVec<[Level; TOP_LEVELS]> {
+        self.bids().take(n.min(TOP_LEVELS)).collect()
+    }
+
+    /// Removes the levels past the best `depth` of each side, for exchanges which don't send removals for the levels
+    /// that fall out of the subscribed depth.
+    ///
+    /// Returns the removed asks and bids with an amount of 0, so they can be sent in an [InputDiff] to other books.
+    pub fn truncate(&mut self, depth: usize) -> (Vec<Level>, Vec<Level>) {
+        let zero: FinitePositiveF64 = 0.0.try_into().expect("0 is a valid FinitePositiveF64");
+        let removed = |level: Level| Level {
+            amount: zero,
+            ..level
+        };
+        let removed = (
+            self.asks().skip(depth).map(removed).collect(),
+            self.bids().skip(depth).map(removed).collect(),
+        );
+        self.asks.truncate(depth);
+        self.bids.truncate_desc(depth);
+        removed
+    }
+
+    /// Returns a new [InputUpdate] from `exchange` with the best [TOP_LEVELS] of each side.
+    pub fn to_update(&self, exchange: Exchange) -> InputUpdate {
+        InputUpdate::new(
+            exchange,
+            self.top_asks(TOP_LEVELS),
+            self.top_bids(TOP_LEVELS),
+        )
+    }
+}
+
+/// Inserts, replaces or removes the `levels` of one side of the book.
+fn apply_levels(side: &mut LevelMap<FinitePositiveF64>, levels: &[Level]) {
+    for level in levels {
+        if Into::<f64>::into(level.amount) == 0. {
+            side.remove(level.price);
+        } else {
+            side.insert(level.price, level.amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arrayvec;
+
+    #[test]
+    fn test_diff_book() {
+        let mut book = DiffBook::new();
+
+        // Diffs before the snapshot are discarded.
+        assert!(!book.apply(&InputDiff::new(
+            Exchange::Coinbase,
+            vec![lvl!(1., 1.)],
+            vec![]
+        )));
+        assert_eq!(book.to_update(Exchange::Coinbase).take().1, arrayvec![]);
+
+        assert!(book.apply(&InputDiff::snapshot(
+            Exchange::Coinbase,
+            vec![lvl!(2., 1.), lvl!(1., 1.), lvl!(3., 0.)],
+            vec![lvl!(0.4, 2.), lvl!(0.5, 1.)],
+        )));
+        assert_eq!(
+            book.to_update(Exchange::Coinbase),
+            InputUpdate::new(
+                Exchange::Coinbase,
+                arrayvec![lvl!(1., 1.), lvl!(2., 1.)],
+                arrayvec![lvl!(0.5, 1.), lvl!(0.4, 2.)],
+            )
+        );
+
+        // Removes 0.5, replaces 2 and adds 1.5, removing a missing level is a no-op.
+        assert!(book.apply(&InputDiff::new(
+            Exchange::Coinbase,
+            vec![lvl!(2., 3.), lvl!(1.5, 1.)],
+            vec![lvl!(0.5, 0.), lvl!(0.1, 0.)],
+        )));
+        assert_eq!(
+            book.to_update(Exchange::Coinbase),
+            InputUpdate::new(
+                Exchange::Coinbase,
+                arrayvec![lvl!(1., 1.), lvl!(1.5, 1.), lvl!(2., 3.)],
+                arrayvec![lvl!(0.4, 2.)],
+            )
+        );
+        assert_eq!(book.top_asks(1), arrayvec![lvl!(1., 1.)]);
+        assert_eq!(
+            book.asks().collect::<Vec<_>>(),
+            vec![lvl!(1., 1.), lvl!(1.5, 1.), lvl!(2., 3.)]
+        );
+        assert_eq!(book.bids().collect::<Vec<_>>(), vec![lvl!(0.4, 2.)]);
+
+        let mut truncated = book.clone();
+        assert_eq!(
+            truncated.truncate(1),
+            (vec![lvl!(1.5, 0.), lvl!(2., 0.)], vec![])
+        );
+        assert_eq!(
+            truncated.to_update(Exchange::Coinbase),
+            InputUpdate::new(
+                Exchange::Coinbase,
+                arrayvec![lvl!(1., 1.)],
+                arrayvec![lvl!(0.4, 2.)]
+            )
+        );
+        // The removed levels bring other books to the same state.
+        let mut other = book.clone();
+        let (asks, bids) = book.clone().truncate(1);
+        other.apply(&InputDiff::new(Exchange::Coinbase, asks, bids));
+        assert_eq!(
+            other.to_update(Exchange::Coinbase),
+            truncated.to_update(Exchange::Coinbase)
+        );
+
+        // Snapshots replace the book.
+        book.apply(
+            &InputUpdate::new(Exchange::Coinbase, arrayvec![lvl!(5., 1.)], arrayvec![]).into(),
+        );
+        assert_eq!(
+            book.to_update(Exchange::Coinbase),
+            InputUpdate::new(Exchange::Coinbase, arrayvec![lvl!(5., 1.)], arrayvec![])
+        );
+
+        book.clear();
+        assert!(!book.apply(&InputDiff::new(
+            Exchange::Coinbase,
+            vec![lvl!(1., 1.)],
+            vec![]
+        )));
+    }
+}
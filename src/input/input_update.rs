@@ -1,19 +1,21 @@
-use super::{Exchange, Level};
-use crate::{is_sorted, proto::orderbook, TOP_LEVELS};
+use super::{AskLevels, BidLevels, Exchange, Level};
+use crate::{is_sorted, is_sorted_strict, merge::microprice, proto::orderbook, TOP_LEVELS};
 use arrayvec::ArrayVec;
 #[cfg(test)]
 use quickcheck::{Arbitrary, Gen};
-#[cfg(test)]
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents the top [TOP_LEVELS] `asks` and `bids` received from `exchange`.
 ///
 /// `asks` and `bids` are assumed to be sorted in `release` and will panic in `debug` if this invariant is broken.
+/// For exchanges which [have unique prices](Exchange::has_unique_prices) they are also assumed not to repeat prices.
 pub struct InputUpdate {
     exchange: Exchange,
     asks: ArrayVec<[Level; TOP_LEVELS]>,
     bids: ArrayVec<[Level; TOP_LEVELS]>,
+    /// Unix milliseconds at which the exchange generated the update, if it provides one.
+    exchange_timestamp_ms: Option<u64>,
 }
 
 impl InputUpdate {
@@ -24,16 +26,102 @@ impl InputUpdate {
         asks: ArrayVec<[Level; TOP_LEVELS]>,
         bids: ArrayVec<[Level; TOP_LEVELS]>,
     ) -> Self {
-        debug_assert!(is_sorted(&asks, Level::cmp_ask), "Unsorted asks");
-        debug_assert!(is_sorted(&bids, Level::cmp_bid), "Unsorted bids");
+        debug_assert!(
+            is_sorted(&asks, Level::cmp_ask),
+            "Unsorted asks from exchange {:?}",
+            exchange
+        );
+        debug_assert!(
+            is_sorted(&bids, Level::cmp_bid),
+            "Unsorted bids from exchange {:?}",
+            exchange
+        );
+        if exchange.has_unique_prices() {
+            debug_assert!(
+                is_sorted_strict(&asks, |a, b| a.price.cmp(&b.price)),
+                "Repeated ask prices from exchange {:?}",
+                exchange
+            );
+            debug_assert!(
+                is_sorted_strict(&bids, |a, b| b.price.cmp(&a.price)),
+                "Repeated bid prices from exchange {:?}",
+                exchange
+            );
+        }
 
         Self {
             exchange,
             asks,
             bids,
+            exchange_timestamp_ms: None,
         }
     }
 
+    /// Returns a new [InputUpdate] with levels received from an exchange, checking the invariants of [InputUpdate::new]
+    /// in every build.
+    ///
+    /// Returns `Err` if `asks` or `bids` are unsorted, or repeat prices for an exchange which
+    /// [has unique prices](Exchange::has_unique_prices).
+    pub fn try_new(
+        exchange: Exchange,
+        asks: ArrayVec<[Level; TOP_LEVELS]>,
+        bids: ArrayVec<[Level; TOP_LEVELS]>,
+    ) -> Result<Self, &'static str> {
+        if !is_sorted(&asks, Level::cmp_ask) {
+            return Err("Unsorted asks");
+        }
+        if !is_sorted(&bids, Level::cmp_bid) {
+            return Err("Unsorted bids");
+        }
+        if exchange.has_unique_prices()
+            && (!is_sorted_strict(&asks, |a, b| a.price.cmp(&b.price))
+                || !is_sorted_strict(&bids, |a, b| b.price.cmp(&a.price)))
+        {
+            return Err("Repeated prices");
+        }
+        Ok(Self::new(exchange, asks, bids))
+    }
+
+    /// Returns a new [InputUpdate] with the provided `asks` in any order and no bids.
+    ///
+    /// Returns `Err` if there are more than [TOP_LEVELS] asks, or repeated prices for an exchange which
+    /// [has unique prices](Exchange::has_unique_prices).
+    pub fn from_ask_iter(
+        exchange: Exchange,
+        asks: impl IntoIterator<Item = Level>,
+    ) -> Result<Self, &'static str> {
+        let asks = into_top_levels(exchange, asks.into_iter().collect::<AskLevels>())?;
+        Ok(Self::new(exchange, asks, ArrayVec::new()))
+    }
+
+    /// Returns a new [InputUpdate] with the provided `bids` in any order and no asks.
+    ///
+    /// Returns `Err` if there are more than [TOP_LEVELS] bids, or repeated prices for an exchange which
+    /// [has unique prices](Exchange::has_unique_prices).
+    pub fn from_bid_iter(
+        exchange: Exchange,
+        bids: impl IntoIterator<Item = Level>,
+    ) -> Result<Self, &'static str> {
+        let bids = into_top_levels(exchange, bids.into_iter().collect::<BidLevels>())?;
+        Ok(Self::new(exchange, ArrayVec::new(), bids))
+    }
+
+    /// Returns the [Exchange] the update comes from.
+    pub fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+
+    /// Sets the unix milliseconds at which the exchange generated the update.
+    pub fn with_exchange_timestamp_ms(mut self, timestamp_ms: u64) -> Self {
+        self.exchange_timestamp_ms = Some(timestamp_ms);
+        self
+    }
+
+    /// Returns the unix milliseconds at which the exchange generated the update, if it provides one.
+    pub fn exchange_timestamp_ms(&self) -> Option<u64> {
+        self.exchange_timestamp_ms
+    }
+
     /// Consumes `self` and returns its contents.
     ///
     /// This approach was taken instead of public fields to be able to better
@@ -49,6 +137,7 @@ impl InputUpdate {
             exchange,
             asks,
             bids,
+            ..
         } = self;
         (exchange, asks, bids)
     }
@@ -60,6 +149,7 @@ impl Into<orderbook::Summary> for InputUpdate {
             asks,
             bids,
             exchange,
+            ..
         } = self;
 
         let into_levels = |levels: ArrayVec<[Level; TOP_LEVELS]>| {
@@ -72,20 +162,126 @@ impl Into<orderbook::Summary> for InputUpdate {
 
         let bids: Vec<orderbook::Level> = into_levels(bids).collect();
 
+        let spread = asks[0].price - bids[0].price;
+        // The reference price of a single exchange is its mid price in every mode.
+        let reference_price = (asks[0].price + bids[0].price) / 2.;
         orderbook::Summary {
-            spread: asks[0].price - bids[0].price,
+            spread,
+            microprice: microprice(&asks, &bids),
             asks,
             bids,
+            per_exchange_spread: vec![(exchange.to_string(), spread)].into_iter().collect(),
+            reference_price,
+            contributing_exchanges: vec![exchange.to_string()],
+            spread_history: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<orderbook::Summary> for InputUpdate {
+    type Error = &'static str;
+    /// Reverses `Into<orderbook::Summary>`, the exchange is taken from the levels.
+    ///
+    /// Returns `Err` if `summary` has no levels, has levels from more than one [Exchange],
+    /// or its levels wouldn't make a valid [InputUpdate].
+    fn try_from(summary: orderbook::Summary) -> Result<Self, Self::Error> {
+        let name = &summary
+            .asks
+            .iter()
+            .chain(summary.bids.iter())
+            .next()
+            .ok_or("Summary without levels")?
+            .exchange;
+        if summary
+            .asks
+            .iter()
+            .chain(summary.bids.iter())
+            .any(|level| level.exchange != *name)
+        {
+            return Err("Levels from multiple exchanges");
         }
+        let exchange: Exchange = name.parse().map_err(|_| "Unknown exchange")?;
+
+        let levels = |levels: &[orderbook::Level],
+                      cmp_fn: fn(&Level, &Level) -> std::cmp::Ordering| {
+            let mut levels = levels
+                .iter()
+                .map(Level::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            levels.sort_by(cmp_fn);
+            into_top_levels(exchange, levels)
+        };
+        Ok(InputUpdate::new(
+            exchange,
+            levels(&summary.asks, Level::cmp_ask)?,
+            levels(&summary.bids, Level::cmp_bid)?,
+        ))
     }
 }
 
+/// Converts sorted `levels` into an [ArrayVec], checking the invariants of [InputUpdate::new].
+fn into_top_levels(
+    exchange: Exchange,
+    levels: impl std::ops::Deref<Target = [Level]>,
+) -> Result<ArrayVec<[Level; TOP_LEVELS]>, &'static str> {
+    if levels.len() > TOP_LEVELS {
+        return Err("Too many levels");
+    }
+    if exchange.has_unique_prices() && levels.windows(2).any(|w| w[0].price == w[1].price) {
+        return Err("Repeated prices");
+    }
+    Ok(levels.iter().copied().collect())
+}
+
+/// Decomposes a merged `summary` back into one [InputUpdate] per [Exchange] present in it, in [Exchange] order.
+///
+/// Only the levels that made it into `summary` can be recovered, so each update holds at most the levels
+/// of its exchange that were among the merged top [TOP_LEVELS].
+/// Returns `Err` if any level has an unknown exchange or contains invalid values, or if an exchange
+/// which [has unique prices](Exchange::has_unique_prices) repeats a price.
+pub fn summary_to_input_updates(
+    summary: &orderbook::Summary,
+) -> Result<Vec<InputUpdate>, &'static str> {
+    let asks = group_by_exchange(&summary.asks)?;
+    let bids = group_by_exchange(&summary.bids)?;
+
+    asks.iter()
+        .zip(bids.iter())
+        .enumerate()
+        .filter(|(_, (asks, bids))| !asks.is_empty() || !bids.is_empty())
+        .map(|(i, (asks, bids))| {
+            let exchange = Exchange::try_from(i as u8).expect("Invalid exchange index");
+            let mut asks = asks.clone();
+            asks.sort_by(Level::cmp_ask);
+            let mut bids = bids.clone();
+            bids.sort_by(Level::cmp_bid);
+            Ok(InputUpdate::new(
+                exchange,
+                into_top_levels(exchange, asks)?,
+                into_top_levels(exchange, bids)?,
+            ))
+        })
+        .collect()
+}
+
+/// Splits `levels` into the levels of each [Exchange], indexed by the [Exchange] discriminant.
+fn group_by_exchange(
+    levels: &[orderbook::Level],
+) -> Result<[ArrayVec<[Level; TOP_LEVELS]>; Exchange::VARIANT_COUNT], &'static str> {
+    let mut groups: [ArrayVec<[Level; TOP_LEVELS]>; Exchange::VARIANT_COUNT] = Default::default();
+    for level in levels {
+        let exchange: Exchange = level.exchange.parse().map_err(|_| "Unknown exchange")?;
+        groups[exchange as usize]
+            .try_push(level.try_into()?)
+            .map_err(|_| "Too many levels")?;
+    }
+    Ok(groups)
+}
+
 #[cfg(test)]
 impl Arbitrary for InputUpdate {
     fn arbitrary(g: &mut Gen) -> Self {
-        let exchange = (u8::arbitrary(g) % Exchange::VARIANT_COUNT as u8)
-            .try_into()
-            .unwrap();
+        let exchange = Exchange::arbitrary(g);
 
         let arbitrary_levels = |g: &mut Gen| -> ArrayVec<[Level; TOP_LEVELS]> {
             let range = 0..(usize::arbitrary(g) % TOP_LEVELS);
@@ -101,11 +297,13 @@ impl Arbitrary for InputUpdate {
             exchange,
             asks,
             bids,
+            exchange_timestamp_ms: None,
         }
     }
 
     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
         let exchange = self.exchange;
+        let exchange_timestamp_ms = self.exchange_timestamp_ms;
         let shrink_arrayvec =
             |arrayvec: &ArrayVec<_>| arrayvec.iter().cloned().collect::<Vec<_>>().shrink();
 
@@ -116,6 +314,7 @@ impl Arbitrary for InputUpdate {
                     exchange,
                     asks: asks.into_iter().collect(),
                     bids: bids.into_iter().collect(),
+                    exchange_timestamp_ms,
                 }),
         )
     }
@@ -124,7 +323,7 @@ impl Arbitrary for InputUpdate {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::arrayvec;
+    use crate::{arrayvec, merge::MergeState};
     use quickcheck_macros::quickcheck;
 
     #[test]
@@ -138,7 +337,12 @@ mod test {
             orderbook::Summary {
                 asks: vec![lvl0!(1., 1.)],
                 bids: vec![lvl0!(0.5, 1.)],
-                spread: 0.5
+                spread: 0.5,
+                microprice: 0.75,
+                per_exchange_spread: vec![("binance".to_string(), 0.5)].into_iter().collect(),
+                reference_price: 0.75,
+                contributing_exchanges: vec!["binance".to_string()],
+                spread_history: Vec::new(),
             }
         );
 
@@ -151,13 +355,150 @@ mod test {
             orderbook::Summary {
                 asks: vec![lvl1!(1., 1.), lvl1!(2., 1.)],
                 bids: vec![lvl1!(0.6, 1.), lvl1!(0.3, 1.)],
-                spread: 0.4
+                spread: 0.4,
+                microprice: 0.8,
+                per_exchange_spread: vec![("bitstamp".to_string(), 0.4)].into_iter().collect(),
+                reference_price: 0.8,
+                contributing_exchanges: vec!["bitstamp".to_string()],
+                spread_history: Vec::new(),
             }
         );
     }
 
     #[test]
-    #[should_panic(expected = "Unsorted asks")]
+    fn test_summary_to_input_updates() {
+        let binance = InputUpdate::new(
+            Exchange::Binance,
+            arrayvec![lvl!(1., 1.), lvl!(3., 2.)],
+            arrayvec![lvl!(0.5, 1.), lvl!(0.2, 1.)],
+        );
+        let bitstamp = InputUpdate::new(
+            Exchange::Bitstamp,
+            arrayvec![lvl!(2., 1.)],
+            arrayvec![lvl!(0.6, 3.), lvl!(0.4, 1.)],
+        );
+        let mut state = MergeState::new();
+        state.update(binance.clone());
+        state.update(bitstamp.clone());
+
+        assert_eq!(
+            summary_to_input_updates(&state.summary()),
+            Ok(vec![binance, bitstamp])
+        );
+
+        // Exchanges without levels are skipped.
+        let summary = orderbook::Summary {
+            asks: vec![lvl1!(2., 1.)],
+            ..Default::default()
+        };
+        assert_eq!(
+            summary_to_input_updates(&summary),
+            Ok(vec![InputUpdate::new(
+                Exchange::Bitstamp,
+                arrayvec![lvl!(2., 1.)],
+                arrayvec![]
+            )])
+        );
+        assert_eq!(
+            summary_to_input_updates(&orderbook::Summary::default()),
+            Ok(vec![])
+        );
+
+        let summary = orderbook::Summary {
+            asks: vec![orderbook::Level {
+                exchange: "ftx".to_string(),
+                price: 1.,
+                amount: 1.,
+            }],
+            ..Default::default()
+        };
+        assert!(summary_to_input_updates(&summary).is_err());
+
+        let summary = orderbook::Summary {
+            asks: vec![lvl0!(1., 1.), lvl0!(1., 2.)],
+            ..Default::default()
+        };
+        assert_eq!(summary_to_input_updates(&summary), Err("Repeated prices"));
+    }
+
+    #[test]
+    fn test_try_from_summary() {
+        let update = InputUpdate::new(
+            Exchange::Bitstamp,
+            arrayvec![lvl!(1., 1.), lvl!(3., 2.)],
+            arrayvec![lvl!(0.5, 1.)],
+        );
+        let summary: orderbook::Summary = update.clone().into();
+        assert_eq!(InputUpdate::try_from(summary), Ok(update));
+
+        let summary = |asks, bids| orderbook::Summary {
+            asks,
+            bids,
+            ..Default::default()
+        };
+        assert_eq!(
+            InputUpdate::try_from(summary(vec![lvl0!(1., 1.)], vec![])),
+            Ok(InputUpdate::new(
+                Exchange::Binance,
+                arrayvec![lvl!(1., 1.)],
+                arrayvec![]
+            ))
+        );
+        assert_eq!(
+            InputUpdate::try_from(summary(vec![], vec![])),
+            Err("Summary without levels")
+        );
+        assert_eq!(
+            InputUpdate::try_from(summary(vec![lvl0!(1., 1.)], vec![lvl1!(0.5, 1.)])),
+            Err("Levels from multiple exchanges")
+        );
+        assert!(InputUpdate::try_from(summary(vec![lvl0!(-1., 1.)], vec![])).is_err());
+        assert_eq!(
+            InputUpdate::try_from(summary(vec![lvl0!(1., 1.), lvl0!(1., 2.)], vec![])),
+            Err("Repeated prices")
+        );
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let levels = vec![lvl!(2., 1.), lvl!(1., 1.), lvl!(3., 1.)];
+        assert_eq!(
+            InputUpdate::from_ask_iter(Exchange::Binance, levels.clone()),
+            Ok(InputUpdate::new(
+                Exchange::Binance,
+                arrayvec![lvl!(1., 1.), lvl!(2., 1.), lvl!(3., 1.)],
+                arrayvec![]
+            ))
+        );
+        assert_eq!(
+            InputUpdate::from_bid_iter(Exchange::Bitstamp, levels),
+            Ok(InputUpdate::new(
+                Exchange::Bitstamp,
+                arrayvec![],
+                arrayvec![lvl!(3., 1.), lvl!(2., 1.), lvl!(1., 1.)]
+            ))
+        );
+
+        let too_many = (0..=TOP_LEVELS).map(|i| lvl!(i as f64, 1.));
+        assert!(InputUpdate::from_ask_iter(Exchange::Binance, too_many.clone()).is_err());
+        assert!(InputUpdate::from_bid_iter(Exchange::Binance, too_many).is_err());
+
+        let repeated = vec![lvl!(1., 1.), lvl!(1., 2.)];
+        assert!(InputUpdate::from_ask_iter(Exchange::Binance, repeated).is_err());
+    }
+
+    #[test]
+    fn test_exchange_timestamp() {
+        let update = InputUpdate::new(Exchange::Bitstamp, arrayvec![], arrayvec![]);
+        assert_eq!(update.exchange_timestamp_ms(), None);
+        assert_eq!(update.exchange(), Exchange::Bitstamp);
+
+        let update = update.with_exchange_timestamp_ms(42);
+        assert_eq!(update.exchange_timestamp_ms(), Some(42));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsorted asks from exchange Binance")]
     fn test_unsorted_asks() {
         InputUpdate::new(
             Exchange::Binance,
@@ -167,7 +508,7 @@ mod test {
     }
 
     #[test]
-    #[should_panic(expected = "Unsorted bids")]
+    #[should_panic(expected = "Unsorted bids from exchange Binance")]
     fn test_unsorted_bids() {
         InputUpdate::new(
             Exchange::Binance,
@@ -176,6 +517,58 @@ mod test {
         );
     }
 
+    #[test]
+    #[should_panic(expected = "Repeated ask prices from exchange Binance")]
+    fn test_repeated_ask_prices() {
+        InputUpdate::new(
+            Exchange::Binance,
+            arrayvec![lvl!(1., 2.), lvl!(1., 1.)],
+            arrayvec![],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Repeated bid prices from exchange Bitstamp")]
+    fn test_repeated_bid_prices() {
+        InputUpdate::new(
+            Exchange::Bitstamp,
+            arrayvec![],
+            arrayvec![lvl!(1., 2.), lvl!(1., 1.)],
+        );
+    }
+
+    #[test]
+    fn test_try_new() {
+        let update = |asks, bids| InputUpdate::try_new(Exchange::Binance, asks, bids);
+        assert_eq!(
+            update(
+                arrayvec![lvl!(1., 1.), lvl!(2., 1.)],
+                arrayvec![lvl!(0.5, 1.)]
+            ),
+            Ok(InputUpdate::new(
+                Exchange::Binance,
+                arrayvec![lvl!(1., 1.), lvl!(2., 1.)],
+                arrayvec![lvl!(0.5, 1.)]
+            ))
+        );
+        assert_eq!(
+            update(arrayvec![lvl!(2., 1.), lvl!(1., 1.)], arrayvec![]),
+            Err("Unsorted asks")
+        );
+        assert_eq!(
+            update(arrayvec![], arrayvec![lvl!(0.4, 1.), lvl!(0.5, 1.)]),
+            Err("Unsorted bids")
+        );
+        assert_eq!(
+            update(arrayvec![lvl!(1., 2.), lvl!(1., 1.)], arrayvec![]),
+            Err("Repeated prices")
+        );
+        assert_eq!(
+            update(arrayvec![], arrayvec![lvl!(0.5, 2.), lvl!(0.5, 1.)]),
+            Err("Repeated prices")
+        );
+    }
+
     #[quickcheck]
     fn test_arbitrary(inputs: Vec<InputUpdate>) {
         for input in inputs {
@@ -1,33 +1,104 @@
 use super::FinitePositiveF64;
-use crate::proto::orderbook;
+use crate::{proto::orderbook, TOP_LEVELS};
+use arrayvec::ArrayVec;
 use num_enum::TryFromPrimitive;
 use parse_display::Display;
 #[cfg(test)]
 use quickcheck::{Arbitrary, Gen};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
+    collections::HashMap,
     convert::{TryFrom, TryInto},
+    str::FromStr,
+    time::Instant,
 };
 use variant_count::VariantCount;
 
-#[derive(Display, PartialEq, Debug, VariantCount, Clone, Copy, TryFromPrimitive)]
+#[derive(Display, PartialEq, Eq, Hash, Debug, VariantCount, Clone, Copy, TryFromPrimitive)]
 #[display(style = "lowercase")]
 #[repr(u8)]
 /// Represents the source exchange for a particular price level.
 pub enum Exchange {
     Binance = 0,
     Bitstamp = 1,
+    Kraken = 2,
+    Coinbase = 3,
 }
 
-#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[derive(Debug, Display, PartialEq, Clone)]
+#[display("Unknown exchange: {0}")]
+/// Error returned when parsing an [Exchange] from a string which isn't the name of any exchange, contains the trimmed string.
+pub struct UnknownExchange(pub String);
+
+impl FromStr for Exchange {
+    type Err = UnknownExchange;
+
+    /// Parses the name of an exchange as displayed, ignoring case and surrounding whitespace.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim();
+        (0..Exchange::VARIANT_COUNT as u8)
+            .map(|exchange| {
+                Exchange::try_from(exchange)
+                    .expect("exchange should be within 0..Exchange::VARIANT_COUNT")
+            })
+            .find(|exchange| exchange.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| UnknownExchange(name.to_string()))
+    }
+}
+
+impl TryFrom<&str> for Exchange {
+    type Error = UnknownExchange;
+
+    /// Same as [Exchange::from_str].
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl Exchange {
+    /// Returns true if the exchange never sends two levels with the same price on the same side,
+    /// in which case repeated prices indicate a data error.
+    pub fn has_unique_prices(self) -> bool {
+        match self {
+            Exchange::Binance => true,
+            Exchange::Bitstamp => true,
+            Exchange::Kraken => true,
+            Exchange::Coinbase => true,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
 /// Represents a price level in an exchange.
 pub struct Level {
     pub price: FinitePositiveF64,
     pub amount: FinitePositiveF64,
 }
 
+#[derive(Debug, Display, PartialEq, Clone, Copy)]
+#[display("Invalid level {field}: {error}")]
+/// Error returned by [Level::try_from_f64_pair], `field` is either `price` or `amount`.
+pub struct LevelError {
+    pub field: &'static str,
+    pub error: &'static str,
+}
+
 impl Level {
+    /// Returns a new [Level] if both `price` and `amount` are valid [FinitePositiveF64].
+    pub fn try_from_f64_pair(price: f64, amount: f64) -> Result<Level, LevelError> {
+        Ok(Level {
+            price: price.try_into().map_err(|error| LevelError {
+                field: "price",
+                error,
+            })?,
+            amount: amount.try_into().map_err(|error| LevelError {
+                field: "amount",
+                error,
+            })?,
+        })
+    }
+
     /// Returns a new [orderbook::Level] with the provided `exchange`.
     pub fn into_orderbook_level(self, exchange: Exchange) -> orderbook::Level {
         let Level { price, amount } = self;
@@ -63,6 +134,64 @@ impl Level {
             c
         }
     }
+
+    /// Orders [Levels](Level) by the time they were first seen, earlier levels first, to break the ties of
+    /// [Level::cmp_ask] and [Level::cmp_bid] in time priority order books.
+    ///
+    /// The time of each level is looked up by price in the timestamps of its own book,
+    /// levels without a timestamp come last.
+    pub fn cmp_by_time_priority(
+        a: &Level,
+        a_timestamps: &HashMap<FinitePositiveF64, Instant>,
+        b: &Level,
+        b_timestamps: &HashMap<FinitePositiveF64, Instant>,
+    ) -> Ordering {
+        match (a_timestamps.get(&a.price), b_timestamps.get(&b.price)) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    /// Returns a new ask [Level] with the effective price paid after a fee of `fee_bps` basis points,
+    /// `price * (1 + fee_bps / 10_000)`.
+    ///
+    /// Returns [None] if the effective price is not finite or not positive.
+    pub fn apply_ask_fee(self, fee_bps: u32) -> Option<Level> {
+        self.with_price_bps(fee_bps as i64)
+    }
+
+    /// Returns a new bid [Level] with the effective price received after a fee of `fee_bps` basis points,
+    /// `price * (1 - fee_bps / 10_000)`.
+    ///
+    /// Returns [None] if the effective price is not finite or not positive.
+    pub fn apply_bid_fee(self, fee_bps: u32) -> Option<Level> {
+        self.with_price_bps(-(fee_bps as i64))
+    }
+
+    /// Returns a new [Level] with the price changed by `bps` basis points if the result is finite and positive.
+    ///
+    /// Divides by 10 000 last, so round fees of round prices are exact.
+    fn with_price_bps(self, bps: i64) -> Option<Level> {
+        self.with_price(Into::<f64>::into(self.price) * (10_000 + bps) as f64 / 10_000.)
+    }
+
+    /// Returns a new [Level] with the price multiplied by `factor` if the result is finite and positive.
+    pub(crate) fn with_price_factor(self, factor: f64) -> Option<Level> {
+        self.with_price(Into::<f64>::into(self.price) * factor)
+    }
+
+    /// Returns a new [Level] with `price` if it's finite and positive.
+    fn with_price(self, price: f64) -> Option<Level> {
+        if price <= 0. {
+            return None;
+        }
+        Some(Level {
+            price: price.try_into().ok()?,
+            amount: self.amount,
+        })
+    }
 }
 
 impl TryFrom<&orderbook::Level> for Level {
@@ -76,6 +205,34 @@ impl TryFrom<&orderbook::Level> for Level {
     }
 }
 
+/// Converts the first [TOP_LEVELS] of `levels` into [Levels](Level), the rest are ignored.
+/// Returns `Err` if any of the converted levels contains invalid values.
+///
+/// Accepts both `&Vec<orderbook::Level>` and `&[orderbook::Level]`,
+/// a `TryFrom` impl isn't possible because both [ArrayVec] and [Vec] are foreign types.
+pub fn try_into_levels<'a>(
+    levels: impl IntoIterator<Item = &'a orderbook::Level>,
+) -> Result<ArrayVec<[Level; TOP_LEVELS]>, &'static str> {
+    levels
+        .into_iter()
+        .take(TOP_LEVELS)
+        .map(Level::try_from)
+        .collect()
+}
+
+#[cfg(test)]
+impl Arbitrary for Exchange {
+    fn arbitrary(g: &mut Gen) -> Self {
+        (u8::arbitrary(g) % Exchange::VARIANT_COUNT as u8)
+            .try_into()
+            .unwrap()
+    }
+    /// Exchanges have no smaller representation.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        quickcheck::empty_shrinker()
+    }
+}
+
 #[cfg(test)]
 impl Arbitrary for Level {
     fn arbitrary(g: &mut Gen) -> Self {
@@ -129,6 +286,30 @@ macro_rules! lvl1 {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_exchange_from_str() {
+        assert_eq!("binance".parse(), Ok(Exchange::Binance));
+        assert_eq!("bitstamp".parse(), Ok(Exchange::Bitstamp));
+        assert_eq!("kraken".parse(), Ok(Exchange::Kraken));
+        assert_eq!("coinbase".parse(), Ok(Exchange::Coinbase));
+        for i in 0..Exchange::VARIANT_COUNT as u8 {
+            let exchange = Exchange::try_from(i).unwrap();
+            assert_eq!(exchange.to_string().parse(), Ok(exchange));
+        }
+
+        assert_eq!(" Binance\n".parse(), Ok(Exchange::Binance));
+        assert_eq!("KRAKEN".parse(), Ok(Exchange::Kraken));
+        assert_eq!(
+            " ftx ".parse::<Exchange>(),
+            Err(UnknownExchange("ftx".to_string()))
+        );
+        assert_eq!(
+            Exchange::try_from("ftx").unwrap_err().to_string(),
+            "Unknown exchange: ftx"
+        );
+        assert_eq!(Exchange::try_from("Coinbase"), Ok(Exchange::Coinbase));
+    }
+
     #[test]
     fn test_into_orderbook_level() {
         assert_eq!(
@@ -159,6 +340,53 @@ mod test {
         assert_eq!(lvl!(1., 3.).cmp_ask(&lvl!(1., 5.)), Ordering::Greater);
     }
 
+    #[test]
+    fn test_cmp_by_time_priority() {
+        let first = Instant::now();
+        let second = first + std::time::Duration::from_millis(1);
+        let level = lvl!(1., 1.);
+        let timestamps = |time| {
+            vec![(level.price, time)]
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert_eq!(
+            Level::cmp_by_time_priority(&level, &timestamps(first), &level, &timestamps(second)),
+            Ordering::Less
+        );
+        assert_eq!(
+            Level::cmp_by_time_priority(&level, &timestamps(second), &level, &timestamps(first)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Level::cmp_by_time_priority(&level, &timestamps(first), &level, &timestamps(first)),
+            Ordering::Equal
+        );
+        // Levels without a timestamp come last.
+        assert_eq!(
+            Level::cmp_by_time_priority(&level, &HashMap::new(), &level, &timestamps(second)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Level::cmp_by_time_priority(&level, &HashMap::new(), &level, &HashMap::new()),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_apply_fee() {
+        assert_eq!(lvl!(100., 3.).apply_ask_fee(0), Some(lvl!(100., 3.)));
+        assert_eq!(lvl!(100., 3.).apply_bid_fee(0), Some(lvl!(100., 3.)));
+        assert_eq!(lvl!(100., 3.).apply_ask_fee(250), Some(lvl!(102.5, 3.)));
+        assert_eq!(lvl!(100., 3.).apply_bid_fee(250), Some(lvl!(97.5, 3.)));
+
+        assert_eq!(lvl!(100., 3.).apply_bid_fee(10_000), None);
+        assert_eq!(lvl!(100., 3.).apply_bid_fee(20_000), None);
+        assert_eq!(lvl!(0., 3.).apply_ask_fee(10), None);
+        assert_eq!(lvl!(f64::MAX, 3.).apply_ask_fee(10), None);
+    }
+
     #[test]
     fn test_try_from() {
         assert_eq!((&lvl0!(1., 4.)).try_into(), Ok(lvl!(1., 4.)));
@@ -167,4 +395,38 @@ mod test {
         assert!(TryInto::<Level>::try_into(&lvl0!(1., -4.)).is_err());
         assert!(TryInto::<Level>::try_into(&lvl0!(1., std::f64::NAN)).is_err());
     }
+
+    #[test]
+    fn test_try_from_f64_pair() {
+        assert_eq!(Level::try_from_f64_pair(1., 4.), Ok(lvl!(1., 4.)));
+        assert_eq!(
+            Level::try_from_f64_pair(-1., std::f64::NAN),
+            Err(LevelError {
+                field: "price",
+                error: "Can't construct FinitePositiveF64 from negative f64"
+            })
+        );
+        let error = Level::try_from_f64_pair(1., std::f64::INFINITY).unwrap_err();
+        assert_eq!(error.field, "amount");
+        assert_eq!(
+            error.to_string(),
+            "Invalid level amount: Can't construct FinitePositiveF64 from non finite f64"
+        );
+    }
+
+    #[test]
+    fn test_try_into_levels() {
+        assert_eq!(try_into_levels(&vec![]), Ok(ArrayVec::new()));
+
+        let levels: Vec<_> = (0..TOP_LEVELS + 5).map(|i| lvl0!(i as f64, 1.)).collect();
+        let expected: ArrayVec<_> = (0..TOP_LEVELS).map(|i| lvl!(i as f64, 1.)).collect();
+        assert_eq!(try_into_levels(&levels), Ok(expected.clone()));
+        assert_eq!(try_into_levels(&levels[..]), Ok(expected));
+
+        assert!(try_into_levels(&[lvl0!(1., 1.), lvl1!(-1., 1.)]).is_err());
+        // Ignored levels aren't validated.
+        let mut levels = levels;
+        levels.push(lvl0!(std::f64::NAN, 1.));
+        assert!(try_into_levels(&levels).is_ok());
+    }
 }
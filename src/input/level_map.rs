@@ -0,0 +1,118 @@
+use super::FinitePositiveF64;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Map from price to an arbitrary value ordered by price, for sources which reconstruct the book from diffs.
+///
+/// [FinitePositiveF64] is [Ord] so it's used as the key directly, without a wrapper like `OrderedFloat`.
+pub struct LevelMap<V: Clone>(BTreeMap<FinitePositiveF64, V>);
+
+impl<V: Clone> LevelMap<V> {
+    /// Returns a new empty [LevelMap].
+    pub fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Sets the value of `price`, returns the previous value if there was one.
+    pub fn insert(&mut self, price: FinitePositiveF64, value: V) -> Option<V> {
+        self.0.insert(price, value)
+    }
+
+    /// Removes `price`, returns its value if it was present.
+    pub fn remove(&mut self, price: FinitePositiveF64) -> Option<V> {
+        self.0.remove(&price)
+    }
+
+    /// Returns the prices and their values in ascending price order, reverse it for the best bids first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (FinitePositiveF64, &V)> {
+        self.0.iter().map(|(price, value)| (*price, value))
+    }
+
+    /// Returns the `n` lowest prices and their values in ascending price order, the best asks.
+    pub fn top_n(&self, n: usize) -> Vec<(FinitePositiveF64, V)> {
+        self.0
+            .iter()
+            .take(n)
+            .map(|(price, value)| (*price, value.clone()))
+            .collect()
+    }
+
+    /// Returns the `n` highest prices and their values in descending price order, the best bids.
+    pub fn top_n_desc(&self, n: usize) -> Vec<(FinitePositiveF64, V)> {
+        self.0
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(price, value)| (*price, value.clone()))
+            .collect()
+    }
+
+    /// Removes every price except the `n` lowest.
+    pub fn truncate(&mut self, n: usize) {
+        if let Some(first_removed) = self.0.keys().nth(n).copied() {
+            self.0.split_off(&first_removed);
+        }
+    }
+
+    /// Removes every price except the `n` highest.
+    pub fn truncate_desc(&mut self, n: usize) {
+        if let Some(last_kept) = self.0.keys().rev().take(n).last().copied() {
+            self.0 = self.0.split_off(&last_kept);
+        } else {
+            self.0.clear();
+        }
+    }
+}
+
+impl<V: Clone> Default for LevelMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn price(price: f64) -> FinitePositiveF64 {
+        price.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_level_map() {
+        let mut map = LevelMap::new();
+        assert!(map.top_n(3).is_empty());
+
+        assert_eq!(map.insert(price(2.), "b"), None);
+        assert_eq!(map.insert(price(1.), "a"), None);
+        assert_eq!(map.insert(price(3.), "c"), None);
+        assert_eq!(map.insert(price(2.), "B"), Some("b"));
+
+        assert_eq!(map.top_n(2), vec![(price(1.), "a"), (price(2.), "B")]);
+        assert_eq!(map.top_n_desc(2), vec![(price(3.), "c"), (price(2.), "B")]);
+        assert_eq!(map.top_n(10).len(), 3);
+        assert!(map.top_n(0).is_empty());
+        assert_eq!(
+            map.iter().rev().collect::<Vec<_>>(),
+            vec![(price(3.), &"c"), (price(2.), &"B"), (price(1.), &"a")]
+        );
+
+        let mut truncated = map.clone();
+        truncated.truncate(2);
+        assert_eq!(truncated.top_n(3), vec![(price(1.), "a"), (price(2.), "B")]);
+        let mut truncated = map.clone();
+        truncated.truncate_desc(2);
+        assert_eq!(truncated.top_n(3), vec![(price(2.), "B"), (price(3.), "c")]);
+        let mut truncated = map.clone();
+        truncated.truncate(5);
+        truncated.truncate_desc(5);
+        assert_eq!(truncated, map);
+        truncated.truncate_desc(0);
+        assert!(truncated.top_n(3).is_empty());
+
+        assert_eq!(map.remove(price(1.)), Some("a"));
+        assert_eq!(map.remove(price(1.)), None);
+        assert_eq!(map.top_n(3), vec![(price(2.), "B"), (price(3.), "c")]);
+    }
+}
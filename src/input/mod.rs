@@ -6,8 +6,16 @@ mod finite_positive_f64;
 pub use finite_positive_f64::*;
 mod input_update;
 pub use input_update::*;
+mod input_diff;
+pub use input_diff::*;
+mod level_map;
+pub use level_map::*;
 mod deserialize_arrayvec;
 pub use deserialize_arrayvec::*;
+mod deserialize_level_tuple;
+pub use deserialize_level_tuple::*;
+mod sorted_levels;
+pub use sorted_levels::*;
 
 #[cfg(test)]
 #[macro_export]
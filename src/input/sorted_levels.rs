@@ -0,0 +1,143 @@
+use super::Level;
+use crate::is_sorted;
+use parse_display::Display;
+use std::{iter::FromIterator, ops::Deref};
+
+#[derive(Debug, Display, PartialEq, Clone, Copy)]
+#[display("Levels are not sorted")]
+/// Returned when constructing [AskLevels] or [BidLevels] from levels which are not sorted.
+pub struct UnsortedError;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// [Vec] of [Levels](Level) which is guaranteed to be sorted by [Level::cmp_ask].
+pub struct AskLevels(Vec<Level>);
+
+impl AskLevels {
+    /// Returns a new [AskLevels] or [UnsortedError] if `levels` are not sorted by [Level::cmp_ask].
+    pub fn new(levels: Vec<Level>) -> Result<Self, UnsortedError> {
+        if is_sorted(&levels, Level::cmp_ask) {
+            Ok(Self(levels))
+        } else {
+            Err(UnsortedError)
+        }
+    }
+
+    /// Returns a new [AskLevels] by sorting `levels` in place.
+    pub fn from_unsorted(mut levels: Vec<Level>) -> Self {
+        levels.sort_by(Level::cmp_ask);
+        Self(levels)
+    }
+
+    /// Consumes `self` and returns the inner sorted [Vec].
+    pub fn into_inner(self) -> Vec<Level> {
+        self.0
+    }
+}
+
+impl FromIterator<Level> for AskLevels {
+    /// Collects and sorts the levels, see [AskLevels::from_unsorted].
+    fn from_iter<I: IntoIterator<Item = Level>>(iter: I) -> Self {
+        Self::from_unsorted(iter.into_iter().collect())
+    }
+}
+
+impl Deref for AskLevels {
+    type Target = [Level];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// [Vec] of [Levels](Level) which is guaranteed to be sorted by [Level::cmp_bid].
+pub struct BidLevels(Vec<Level>);
+
+impl BidLevels {
+    /// Returns a new [BidLevels] or [UnsortedError] if `levels` are not sorted by [Level::cmp_bid].
+    pub fn new(levels: Vec<Level>) -> Result<Self, UnsortedError> {
+        if is_sorted(&levels, Level::cmp_bid) {
+            Ok(Self(levels))
+        } else {
+            Err(UnsortedError)
+        }
+    }
+
+    /// Returns a new [BidLevels] by sorting `levels` in place.
+    pub fn from_unsorted(mut levels: Vec<Level>) -> Self {
+        levels.sort_by(Level::cmp_bid);
+        Self(levels)
+    }
+
+    /// Consumes `self` and returns the inner sorted [Vec].
+    pub fn into_inner(self) -> Vec<Level> {
+        self.0
+    }
+}
+
+impl FromIterator<Level> for BidLevels {
+    /// Collects and sorts the levels, see [BidLevels::from_unsorted].
+    fn from_iter<I: IntoIterator<Item = Level>>(iter: I) -> Self {
+        Self::from_unsorted(iter.into_iter().collect())
+    }
+}
+
+impl Deref for BidLevels {
+    type Target = [Level];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert!(AskLevels::new(vec![]).is_ok());
+        assert!(BidLevels::new(vec![]).is_ok());
+
+        assert_eq!(
+            &*AskLevels::new(vec![lvl!(1., 1.), lvl!(2., 1.)]).unwrap(),
+            &[lvl!(1., 1.), lvl!(2., 1.)]
+        );
+        assert_eq!(
+            &*BidLevels::new(vec![lvl!(2., 1.), lvl!(1., 1.)]).unwrap(),
+            &[lvl!(2., 1.), lvl!(1., 1.)]
+        );
+
+        assert_eq!(
+            AskLevels::new(vec![lvl!(2., 1.), lvl!(1., 1.)]),
+            Err(UnsortedError)
+        );
+        assert_eq!(
+            BidLevels::new(vec![lvl!(1., 1.), lvl!(2., 1.)]),
+            Err(UnsortedError)
+        );
+    }
+
+    #[test]
+    fn test_from_unsorted() {
+        assert_eq!(
+            AskLevels::from_unsorted(vec![lvl!(2., 1.), lvl!(1., 1.)]).into_inner(),
+            vec![lvl!(1., 1.), lvl!(2., 1.)]
+        );
+        assert_eq!(
+            BidLevels::from_unsorted(vec![lvl!(1., 1.), lvl!(2., 1.)]).into_inner(),
+            vec![lvl!(2., 1.), lvl!(1., 1.)]
+        );
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let levels = || vec![lvl!(1., 1.), lvl!(3., 1.), lvl!(2., 1.)].into_iter();
+        assert_eq!(
+            levels().collect::<AskLevels>().into_inner(),
+            vec![lvl!(1., 1.), lvl!(2., 1.), lvl!(3., 1.)]
+        );
+        assert_eq!(
+            levels().collect::<BidLevels>().into_inner(),
+            vec![lvl!(3., 1.), lvl!(2., 1.), lvl!(1., 1.)]
+        );
+    }
+}
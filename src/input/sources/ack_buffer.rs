@@ -0,0 +1,141 @@
+use std::{collections::VecDeque, time::Duration};
+use tokio::time::Instant;
+
+#[derive(Debug)]
+/// Buffers the messages received before an exchange acknowledges a subscription,
+/// so they can be applied in order once the acknowledgement arrives instead of being
+/// dropped or applied before the exchange guarantees their order.
+///
+/// At most `capacity` messages are buffered, once full the oldest ones are dropped.
+pub struct AckBuffer<T> {
+    acked: bool,
+    capacity: usize,
+    pending: VecDeque<T>,
+    /// Time the exchange has to acknowledge the subscription, [None] waits forever.
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl<T> AckBuffer<T> {
+    /// Returns a new [AckBuffer] waiting for an acknowledgement.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            acked: false,
+            capacity,
+            pending: VecDeque::with_capacity(capacity),
+            timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// Expects the acknowledgement within `timeout` of the creation of the [AckBuffer] or the last [AckBuffer::reset].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Returns the instant by which the subscription should be acknowledged,
+    /// [None] if it already has been or there is no timeout.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.acked {
+            None
+        } else {
+            self.deadline
+        }
+    }
+
+    /// Returns `message` if the subscription has been acknowledged, otherwise buffers it and returns [None].
+    pub fn push(&mut self, message: T) -> Option<T> {
+        if self.acked {
+            return Some(message);
+        }
+        if self.pending.len() >= self.capacity {
+            self.pending.pop_front();
+        }
+        if self.capacity > 0 {
+            self.pending.push_back(message);
+        }
+        None
+    }
+
+    /// Marks the subscription as acknowledged and returns the buffered messages in the order they were received.
+    pub fn ack(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.acked = true;
+        self.pending.drain(..)
+    }
+
+    /// Returns true if the subscription has been acknowledged.
+    pub fn is_acked(&self) -> bool {
+        self.acked
+    }
+
+    /// Discards the buffered messages and waits for a new acknowledgement, should be called after reconnecting.
+    pub fn reset(&mut self) {
+        self.acked = false;
+        self.pending.clear();
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buffer_until_ack() {
+        let mut buffer = AckBuffer::new(10);
+        assert_eq!(buffer.push(1), None);
+        assert_eq!(buffer.push(2), None);
+        assert!(!buffer.is_acked());
+
+        assert_eq!(buffer.ack().collect::<Vec<_>>(), vec![1, 2]);
+        assert!(buffer.is_acked());
+
+        assert_eq!(buffer.push(3), Some(3));
+        assert_eq!(buffer.ack().count(), 0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut buffer = AckBuffer::new(10);
+        assert_eq!(buffer.push(1), None);
+        buffer.reset();
+        assert_eq!(buffer.ack().count(), 0);
+
+        buffer.reset();
+        assert_eq!(buffer.push(2), None);
+        assert_eq!(buffer.ack().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        tokio::time::pause();
+        let mut buffer = AckBuffer::<()>::new(10);
+        assert_eq!(buffer.deadline(), None);
+
+        let mut buffer = AckBuffer::<()>::new(10).with_timeout(Duration::from_secs(5));
+        let start = Instant::now();
+        assert_eq!(buffer.deadline(), Some(start + Duration::from_secs(5)));
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        buffer.reset();
+        assert_eq!(buffer.deadline(), Some(start + Duration::from_secs(7)));
+
+        assert_eq!(buffer.ack().count(), 0);
+        assert_eq!(buffer.deadline(), None);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buffer = AckBuffer::new(2);
+        for i in 0..5 {
+            assert_eq!(buffer.push(i), None);
+        }
+        assert_eq!(buffer.ack().collect::<Vec<_>>(), vec![3, 4]);
+
+        let mut buffer = AckBuffer::new(0);
+        assert_eq!(buffer.push(1), None);
+        assert_eq!(buffer.ack().count(), 0);
+    }
+}
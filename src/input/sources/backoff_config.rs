@@ -0,0 +1,89 @@
+use backoff::ExponentialBackoff;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Declarative parameters for the [ExponentialBackoff] used by the sources to retry connections.
+pub struct BackoffConfig {
+    /// Delay before the first retry.
+    pub initial_interval: Duration,
+    /// Upper bound of the delay between retries, before randomization.
+    pub max_interval: Duration,
+    /// Factor by which the delay grows after every retry.
+    pub multiplier: f64,
+    /// Time after which the source stops retrying, [None] retries forever.
+    pub max_elapsed: Option<Duration>,
+}
+
+impl BackoffConfig {
+    /// Returns a new [ExponentialBackoff] with the configured parameters.
+    pub fn build(&self) -> ExponentialBackoff {
+        ExponentialBackoff {
+            current_interval: self.initial_interval,
+            initial_interval: self.initial_interval,
+            max_interval: self.max_interval,
+            multiplier: self.multiplier,
+            max_elapsed_time: self.max_elapsed,
+            ..Default::default()
+        }
+    }
+
+    /// Returns a closure which builds a new [ExponentialBackoff] on every call, as expected by the sources.
+    pub fn factory(&self) -> impl Fn() -> ExponentialBackoff + Clone {
+        let config = *self;
+        move || config.build()
+    }
+}
+
+impl Default for BackoffConfig {
+    /// Same parameters as [ExponentialBackoff::default].
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_millis(500),
+            max_interval: Duration::from_secs(60),
+            multiplier: 1.5,
+            max_elapsed: Some(Duration::from_secs(15 * 60)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use backoff::backoff::Backoff;
+
+    #[test]
+    fn test_bounds() {
+        let config = BackoffConfig {
+            initial_interval: Duration::from_millis(100),
+            max_interval: Duration::from_secs(1),
+            multiplier: 2.,
+            max_elapsed: None,
+        };
+        let mut backoff = config.factory()();
+        let max_delay = config
+            .max_interval
+            .mul_f64(1. + backoff.randomization_factor);
+
+        for _ in 0..20 {
+            let delay = backoff
+                .next_backoff()
+                .expect("Backoff should retry forever");
+            assert!(delay <= max_delay);
+            assert!(backoff.current_interval <= config.max_interval);
+        }
+        assert_eq!(backoff.current_interval, config.max_interval);
+
+        backoff.reset();
+        assert_eq!(backoff.current_interval, config.initial_interval);
+    }
+
+    #[test]
+    fn test_default() {
+        let expected = ExponentialBackoff::default();
+        let backoff = BackoffConfig::default().build();
+        assert_eq!(backoff.initial_interval, expected.initial_interval);
+        assert_eq!(backoff.max_interval, expected.max_interval);
+        assert_eq!(backoff.multiplier, expected.multiplier);
+        assert_eq!(backoff.max_elapsed_time, expected.max_elapsed_time);
+    }
+}
@@ -1,91 +1,475 @@
+//! Source for the Binance [partial book depth streams](https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md#partial-book-depth-streams).
+//!
+//! Connects to `wss://stream.binance.com:9443/ws/{symbol}@depth{levels}@100ms`, the subscription is encoded in the url
+//! so nothing is sent after connecting. Every text frame is a full snapshot of the top `levels` of the book,
+//! `{"lastUpdateId":1,"bids":[["0.5","1"]],"asks":[["1","1"]]}`, with prices and amounts as strings,
+//! only the best [TOP_LEVELS] of each side are kept.
+//!
+//! [get_combined_stream] subscribes to several pairs on one connection through the
+//! [combined stream](https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md#general-wss-information)
+//! endpoint, `wss://stream.binance.com:9443/stream?streams={symbol}@depth10@100ms/...`, which wraps each message in an envelope
+//! with the name of its stream, `{"stream":"ethbtc@depth10@100ms","data":{"lastUpdateId":1,...}}`.
+//!
+//! Ping, pong and binary frames are ignored, tungstenite answers pings on its own. Text frames which don't parse
+//! and connection errors reconnect with [ReconnectReason::Error], close frames are handled according to [close_action]
+//! and, if [SourceConfig::stall_threshold] is set, a feed which repeats the same `lastUpdateId` and levels reconnects
+//! with [ReconnectReason::Stalled].
+//!
+//! Connections are retried with the provided backoff, which panics if it gives up, and are made at most once every
+//! [SourceConfig::min_reconnect_interval]. The stream only ends if Binance closes the connection with an unrecoverable error.
+
 use super::super::{DeserializeArrayVec, Exchange, InputUpdate, Level};
+use super::{
+    close_action, connect, forward_raw, CloseAction, DuplicatePrices, ReconnectLimiter,
+    ReconnectReason, SourceConfig, SourceError, StallDetector,
+};
 use crate::TOP_LEVELS;
 use async_stream::stream;
 use backoff::{backoff::Backoff, tokio::retry_notify};
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
+use tokio::time::sleep;
 use tokio_stream::{Stream, StreamExt};
-use tokio_tungstenite::connect_async;
 use tungstenite::Message;
 use url::Url;
 
 #[derive(Deserialize)]
 /// Represents websocket messages from Binance.
 struct BinanceInput {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: u64,
     asks: DeserializeArrayVec<[Level; TOP_LEVELS]>,
     bids: DeserializeArrayVec<[Level; TOP_LEVELS]>,
 }
 
+impl BinanceInput {
+    /// Returns a new [InputUpdate] with repeated prices handled according to `duplicate_prices`,
+    /// see [InputUpdate::try_new] for the errors.
+    fn into_update(self, duplicate_prices: DuplicatePrices) -> Result<InputUpdate, &'static str> {
+        let BinanceInput { asks, bids, .. } = self;
+
+        InputUpdate::try_new(
+            Exchange::Binance,
+            duplicate_prices.collapse(asks.into()),
+            duplicate_prices.collapse(bids.into()),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+/// Represents websocket messages from the Binance combined stream endpoint, `data` comes from the subscription named `stream`.
+///
+/// Messages from the single stream endpoint are wrapped without a `stream`, see [parse_single_message].
+struct BinanceCombinedInput {
+    stream: Option<String>,
+    data: BinanceInput,
+}
+
+/// Parses a websocket message into a [BinanceCombinedInput], returns [None] for messages that should be ignored.
+type Parser =
+    fn(Result<Message, tungstenite::Error>) -> Option<Result<BinanceCombinedInput, SourceError>>;
+
+#[cfg(test)]
 impl Into<InputUpdate> for BinanceInput {
     fn into(self) -> InputUpdate {
-        let BinanceInput { asks, bids } = self;
-
-        // We assume that asks and bids come sorted from Binance,
-        // this call will panic in `debug` mode if that is not the case.
-        InputUpdate::new(Exchange::Binance, asks.into(), bids.into())
+        self.into_update(DuplicatePrices::Keep)
+            .expect("Invalid update from Binance")
     }
 }
 
 /// Establishes a new connection to Binance and returns a [Stream] of [BinanceInput].
+///
+/// Binance subscriptions are encoded in the url, so connection attempts are rate limited by [SourceConfig::throttle].
+/// Waits for `limiter` before connecting. Raw text frames are forwarded to [SourceConfig::raw_messages] before parsing.
 async fn get_stream_inner<B: Backoff>(
     url: Url,
+    parse: Parser,
     // Backoff is not Clone.
     backoff: impl Fn() -> B,
-) -> impl Stream<Item = Result<BinanceInput, tungstenite::Error>> {
+    config: &SourceConfig,
+    limiter: &ReconnectLimiter,
+) -> impl Stream<Item = Result<BinanceCombinedInput, SourceError>> {
+    limiter.wait().await;
     retry_notify(
         backoff(),
         || async {
-            let (socket, _) = connect_async(url.clone()).await?;
+            config.throttle.acquire().await;
+            let socket = connect(&url, config.connection_timeout).await?;
             Ok(socket)
         },
         |err, _| eprintln!("Error creating Binance connection: {}, retrying", err),
     )
     .await
     .expect("Could not open connection to Binance")
-    .filter_map(|item| {
-        match item {
-            Ok(Message::Text(mut text)) => match simd_json::from_str::<BinanceInput>(&mut text) {
-                Ok(input) => Some(Ok(input)),
-                Err(err) => Some(Err(tungstenite::Error::Protocol(Cow::Owned(
-                    err.to_string(),
-                )))),
-            },
-            Ok(Message::Close(_)) => Some(Err(tungstenite::Error::ConnectionClosed)),
-            Err(err) => Some(Err(err)),
-            Ok(_) => {
-                // Ignore ping, pong and binary messages
-                None
-            }
+    .filter_map({
+        let raw_messages = config.raw_messages.clone();
+        move |item| {
+            forward_raw(raw_messages.as_ref(), &item);
+            parse(item)
         }
     })
 }
 
+/// Parses a websocket message from Binance, returns [None] for messages that should be ignored.
+fn parse_message(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<BinanceInput, SourceError>> {
+    parse_frame(item)
+}
+
+/// Same as [parse_message] but wraps the message in a [BinanceCombinedInput] without a stream name,
+/// for the single stream endpoint.
+fn parse_single_message(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<BinanceCombinedInput, SourceError>> {
+    parse_message(item).map(|result| result.map(|data| BinanceCombinedInput { stream: None, data }))
+}
+
+/// Parses a websocket message from the Binance combined stream endpoint, returns [None] for messages that should be ignored.
+fn parse_combined_message(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<BinanceCombinedInput, SourceError>> {
+    parse_frame(item)
+}
+
+/// Parses the text frames of `item` as `T`, returns [None] for messages that should be ignored.
+fn parse_frame<T: DeserializeOwned>(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<T, SourceError>> {
+    match item {
+        Ok(Message::Text(mut text)) => match simd_json::from_str::<T>(&mut text) {
+            Ok(input) => Some(Ok(input)),
+            Err(err) => Some(Err(tungstenite::Error::Protocol(Cow::Owned(
+                err.to_string(),
+            ))
+            .into())),
+        },
+        Ok(Message::Close(frame)) => Some(Err(SourceError::Closed(close_action(frame.as_ref())))),
+        Err(err) => Some(Err(err.into())),
+        Ok(_) => {
+            // Ignore ping, pong and binary messages
+            None
+        }
+    }
+}
+
 /// Creates a new [InputUpdate] [Stream] from the provided `pair` by connecting to the Binance [websocket API](https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md#partial-book-depth-streams).
 /// The stream is resilient and will retry if errors happen. If the pair is not valid, the stream will be empty, this is because there is no signal
 // from Binance that indicates a pair is not valid.
+/// Connection attempts are rate limited by [SourceConfig::throttle] so reconnection storms don't exceed Binance's limits.
 pub fn get_stream<B: Backoff>(
     pair: String,
     backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    get_depth_stream(pair, 10, backoff, config)
+}
+
+/// Same as [get_stream] but subscribes to the 20 level depth stream, only the best [TOP_LEVELS] are kept.
+///
+/// The rest of the levels are skipped by [DeserializeArrayVec] during parsing, so the output is the same as [get_stream]
+/// for the same market state.
+pub fn get_full_depth_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    get_depth_stream(pair, 20, backoff, config)
+}
+
+/// Creates a new [InputUpdate] [Stream] from the Binance partial book depth stream with `depth` levels.
+fn get_depth_stream<B: Backoff>(
+    pair: String,
+    depth: u8,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
 ) -> impl Stream<Item = InputUpdate> {
     let url = Url::parse(&format!(
-        "wss://stream.binance.com:9443/ws/{}@depth10@100ms",
-        pair
+        "wss://stream.binance.com:9443/ws/{}@depth{}@100ms",
+        Exchange::Binance.symbol(&pair),
+        depth
     ))
     .expect("Invalid pair");
 
+    get_url_stream(url, parse_single_message, backoff, config).map(|(_, update)| update)
+}
+
+/// Creates a new [Stream] of `(pair, update)` from the 10 level partial book depth streams of every pair in `pairs`,
+/// multiplexed on a single connection to the Binance combined stream endpoint.
+///
+/// Behaves like [get_stream] otherwise, a stall in the stream of any pair reconnects every pair.
+pub fn get_combined_stream<B: Backoff>(
+    pairs: &[String],
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = (String, InputUpdate)> {
+    let streams = combined_streams(pairs);
+    let names = streams
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let url = Url::parse(&format!(
+        "wss://stream.binance.com:9443/stream?streams={}",
+        names.join("/")
+    ))
+    .expect("Invalid pair");
+
+    demultiplex(
+        get_url_stream(url, parse_combined_message, backoff, config),
+        streams,
+    )
+}
+
+/// Returns the name of the combined stream subscription of each pair in `pairs`, with the pair.
+fn combined_streams(pairs: &[String]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|pair| {
+            (
+                format!("{}@depth10@100ms", Exchange::Binance.symbol(pair)),
+                pair.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Replaces the stream name of every update in `stream` with its pair in `streams`, updates from unknown streams are dropped.
+fn demultiplex(
+    stream: impl Stream<Item = (Option<String>, InputUpdate)>,
+    streams: Vec<(String, String)>,
+) -> impl Stream<Item = (String, InputUpdate)> {
+    let streams = streams.into_iter().collect::<HashMap<_, _>>();
+    stream.filter_map(move |(name, update)| Some((streams.get(&name?)?.clone(), update)))
+}
+
+/// Creates a new [Stream] of `(stream name, update)` from the Binance websocket at `url`, parsing its messages with `parse`.
+///
+/// The stream name is [None] for messages from the single stream endpoint, see [parse_single_message].
+fn get_url_stream<B: Backoff>(
+    url: Url,
+    parse: Parser,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = (Option<String>, InputUpdate)> {
     stream! {
+        let limiter = ReconnectLimiter::new(config.min_reconnect_interval);
         loop{
-            let mut s = get_stream_inner(url.clone(),backoff.clone()).await;
+            let mut s = get_stream_inner(url.clone(),parse,backoff.clone(),&config,&limiter).await;
+            // Messages without a stream name come from the single stream endpoint,
+            // each stream of a combined connection repeats its own updates.
+            let mut stall = StallDetector::new(config.stall_threshold);
+            let mut stalls: HashMap<String, StallDetector<_>> = HashMap::new();
             while let Some(value) = s.next().await {
                 match value {
-                    Ok(update) => yield update.into(),
+                    Ok(BinanceCombinedInput { stream, data }) => {
+                        config.record_message();
+                        let last_update_id = data.last_update_id;
+                        let update = match data.into_update(config.duplicate_prices) {
+                            Ok(update) => update,
+                            Err(err) => {
+                                eprintln!("Invalid update from Binance: {}, skipping", err);
+                                continue;
+                            }
+                        };
+                        let detector = match &stream {
+                            Some(name) => {
+                                // Only allocate the name the first time a stream is seen.
+                                if !stalls.contains_key(name.as_str()) {
+                                    stalls.insert(name.clone(), StallDetector::new(config.stall_threshold));
+                                }
+                                stalls.get_mut(name.as_str()).expect("stall detector was just inserted")
+                            }
+                            None => &mut stall,
+                        };
+                        let keyed = (last_update_id, update);
+                        if detector.is_stalled(&keyed) {
+                            eprintln!("Binance stream stalled, restarting");
+                            config.notify_reconnect(Exchange::Binance, ReconnectReason::Stalled);
+                            stall.reset();
+                            stalls.clear();
+                            s = get_stream_inner(url.clone(),parse,backoff.clone(),&config,&limiter).await;
+                        } else {
+                            yield (stream, keyed.1);
+                        }
+                    }
+                    Err(SourceError::Closed(CloseAction::Terminate)) => {
+                        eprintln!("Binance closed the connection with an unrecoverable error, stopping");
+                        return;
+                    }
+                    Err(SourceError::Closed(action)) => {
+                        eprintln!("Binance closed the connection, reconnecting");
+                        config.notify_reconnect(Exchange::Binance, ReconnectReason::Closed);
+                        if let CloseAction::Delay(delay) = action {
+                            sleep(delay).await;
+                        }
+                        stall.reset();
+                        stalls.clear();
+                        s = get_stream_inner(url.clone(),parse,backoff.clone(),&config,&limiter).await;
+                    }
                     Err(err) => {
                         eprintln!("Unexpected error in Binance stream: {}, restarting",err);
-                        s = get_stream_inner(url.clone(),backoff.clone()).await;
+                        config.notify_reconnect(Exchange::Binance, ReconnectReason::Error);
+                        stall.reset();
+                        stalls.clear();
+                        s = get_stream_inner(url.clone(),parse,backoff.clone(),&config,&limiter).await;
                     }
                 }
             }
             eprintln!("Binance stream stopped unexpectedly, restarting");
+            config.notify_reconnect(Exchange::Binance, ReconnectReason::StreamEnded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BackoffConfig, MockWsServer, TokenBucket};
+    use super::*;
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+    /// Returns a Binance depth message with `depth` levels per side from the same market state.
+    fn depth_message(depth: usize) -> String {
+        let levels = |prices: Vec<usize>| {
+            prices
+                .iter()
+                .map(|price| format!(r#"["{}","{}"]"#, price, price % 3 + 1))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            r#"{{"lastUpdateId":1,"bids":[{}],"asks":[{}]}}"#,
+            levels((0..depth).map(|i| 100 - i).collect()),
+            levels((0..depth).map(|i| 101 + i).collect()),
+        )
+    }
+
+    #[test]
+    fn test_full_depth() {
+        let parse = |depth| -> InputUpdate {
+            match parse_message(Ok(Message::Text(depth_message(depth)))) {
+                Some(Ok(input)) => input.into(),
+                _ => panic!("Invalid depth message"),
+            }
+        };
+        let (_, asks, bids) = parse(20).take();
+        assert_eq!(asks.len(), TOP_LEVELS);
+        assert_eq!(bids.len(), TOP_LEVELS);
+
+        assert_eq!(parse(20), parse(10));
+    }
+
+    #[test]
+    fn test_duplicate_prices() {
+        let parse = |duplicate_prices: DuplicatePrices| {
+            match parse_message(Ok(Message::Text(
+                r#"{"lastUpdateId":1,"bids":[["0.5","1"],["0.5","2"]],"asks":[["1","3"],["1","1"],["2","1"]]}"#
+                    .to_string(),
+            ))) {
+                Some(Ok(input)) => input.into_update(duplicate_prices).map(InputUpdate::take),
+                _ => panic!("Invalid depth message"),
+            }
+        };
+        let (_, asks, bids) = parse(DuplicatePrices::Sum).unwrap();
+        assert_eq!(asks.as_slice(), &[lvl!(1., 4.), lvl!(2., 1.)]);
+        assert_eq!(bids.as_slice(), &[lvl!(0.5, 3.)]);
+
+        let (_, asks, bids) = parse(DuplicatePrices::Largest).unwrap();
+        assert_eq!(asks.as_slice(), &[lvl!(1., 3.), lvl!(2., 1.)]);
+        assert_eq!(bids.as_slice(), &[lvl!(0.5, 2.)]);
+
+        assert_eq!(parse(DuplicatePrices::Keep), Err("Repeated prices"));
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert!(matches!(
+            parse_message(Ok(Message::Text(
+                r#"{"lastUpdateId":1,"bids":[["0.5","1"]],"asks":[["1","1"]]}"#.to_string()
+            ))),
+            Some(Ok(_))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Text("{}".to_string()))),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(parse_message(Ok(Message::Ping(vec![]))).is_none());
+
+        assert!(matches!(
+            parse_message(Ok(Message::Close(None))),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Away,
+                reason: Cow::Borrowed("")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: Cow::Borrowed("Too many requests")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Delay(_))))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Protocol,
+                reason: Cow::Borrowed("")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Terminate)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_combined_stream() {
+        let envelope = |stream: &str, ask: &str| {
+            Message::Text(format!(
+                r#"{{"stream":"{}","data":{{"lastUpdateId":1,"bids":[["0.5","1"]],"asks":[["{}","1"]]}}}}"#,
+                stream, ask
+            ))
+        };
+        let server = MockWsServer::start(vec![vec![
+            envelope("ethbtc@depth10@100ms", "1"),
+            // Not subscribed, dropped.
+            envelope("ltcbtc@depth10@100ms", "3"),
+            envelope("btcusdt@depth10@100ms", "2"),
+        ]])
+        .await;
+
+        let streams = combined_streams(&["ETHBTC".to_string(), "BTCUSDT".to_string()]);
+        assert_eq!(
+            streams,
+            vec![
+                ("ethbtc@depth10@100ms".to_string(), "ETHBTC".to_string()),
+                ("btcusdt@depth10@100ms".to_string(), "BTCUSDT".to_string()),
+            ]
+        );
+        let config = SourceConfig {
+            throttle: TokenBucket::new(2, Duration::from_secs(1)),
+            min_reconnect_interval: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let stream = demultiplex(
+            get_url_stream(
+                server.url(),
+                parse_combined_message,
+                BackoffConfig::default().factory(),
+                config,
+            ),
+            streams,
+        );
+        tokio::pin!(stream);
+        for (pair, ask) in vec![("ETHBTC", 1.), ("BTCUSDT", 2.)] {
+            let (received, update) = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .expect("Timed out waiting for an update")
+                .unwrap();
+            assert_eq!(received, pair);
+            assert_eq!(update.take().1.as_slice(), &[lvl!(ask, 1.)]);
         }
     }
 }
@@ -1,20 +1,52 @@
-use super::super::{DeserializeArrayVec, Exchange, InputUpdate, Level};
+//! Source for the Bitstamp [websocket API v2](https://www.bitstamp.net/websocket/v2/).
+//!
+//! Connects to `wss://ws.bitstamp.net` and sends [subscribe_message] for the `order_book_{symbol}` channel,
+//! or the one provided to [get_channel_stream]. Handled events:
+//! - `data`: a full snapshot of the top 100 levels of the book,
+//!   `{"event":"data","channel":"order_book_ethbtc","data":{"timestamp":"1","microtimestamp":"1000000","bids":[["0.5","1"]],"asks":[["1","1"]]}}`,
+//!   with prices and amounts as strings, only the best [TOP_LEVELS] of each side are kept.
+//! - `bts:subscription_succeeded`: data received before it is buffered, since its order is only guaranteed afterwards.
+//!   If it doesn't arrive within [SourceConfig::subscribe_timeout] the source reconnects with [ReconnectReason::AckTimeout].
+//! - `bts:request_reconnect`: sent before maintenance, the source reconnects with [ReconnectReason::Requested].
+//!
+//! Ping, pong and binary frames are ignored, tungstenite answers pings on its own. Any other event, like
+//! `bts:error`, fails to parse and reconnects with [ReconnectReason::Error] like connection errors do,
+//! close frames are handled according to [close_action] and, if [SourceConfig::stall_threshold] is set,
+//! a feed which repeats the same `microtimestamp` and levels reconnects with [ReconnectReason::Stalled].
+//!
+//! Connections are retried with the provided backoff, which panics if it gives up, and are made at most once every
+//! [SourceConfig::min_reconnect_interval]. The stream only ends if Bitstamp closes the connection with an unrecoverable error.
+
+use super::super::{DeserializeArrayVec, DeserializeLevelTuple, Exchange, InputUpdate, Level};
+use super::{
+    close_action, connect, forward_raw, next_before, AckBuffer, CloseAction, DuplicatePrices,
+    ReconnectLimiter, ReconnectReason, SourceConfig, SourceError, StallDetector,
+};
 use crate::TOP_LEVELS;
+use arrayvec::ArrayVec;
 use async_stream::stream;
 use backoff::{backoff::Backoff, tokio::retry_notify};
 use futures_util::SinkExt;
-use serde::Deserialize;
-use std::borrow::Cow;
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::sleep;
 use tokio_stream::{Stream, StreamExt};
-use tokio_tungstenite::connect_async;
 use tungstenite::Message;
 use url::Url;
 
 #[derive(Deserialize)]
 /// Represents data inside Bitstamp `data` messages.
+///
+/// Levels are deserialized as tuples so the order ids in `detail_order_book` messages are ignored.
 struct BitstampData {
-    asks: DeserializeArrayVec<[Level; TOP_LEVELS]>,
-    bids: DeserializeArrayVec<[Level; TOP_LEVELS]>,
+    #[serde(rename = "timestamp", deserialize_with = "deserialize_seconds_as_ms")]
+    timestamp_ms: u64,
+    microtimestamp: String,
+    asks: DeserializeArrayVec<[DeserializeLevelTuple; TOP_LEVELS]>,
+    bids: DeserializeArrayVec<[DeserializeLevelTuple; TOP_LEVELS]>,
 }
 
 #[derive(Deserialize)]
@@ -31,33 +63,115 @@ enum BitstampInput {
     SubSuccess,
 }
 
+impl BitstampData {
+    /// Returns a new [InputUpdate] with repeated prices handled according to `duplicate_prices`,
+    /// see [InputUpdate::try_new] for the errors.
+    fn into_update(self, duplicate_prices: DuplicatePrices) -> Result<InputUpdate, &'static str> {
+        let BitstampData {
+            timestamp_ms,
+            asks,
+            bids,
+            ..
+        } = self;
+        let levels = |levels: DeserializeArrayVec<_>| {
+            let levels: ArrayVec<[DeserializeLevelTuple; TOP_LEVELS]> = levels.into();
+            duplicate_prices.collapse(levels.into_iter().map(Into::<Level>::into).collect())
+        };
+        InputUpdate::try_new(Exchange::Bitstamp, levels(asks), levels(bids))
+            .map(|update| update.with_exchange_timestamp_ms(timestamp_ms))
+    }
+}
+
+#[cfg(test)]
+impl From<BitstampData> for InputUpdate {
+    /// Same as [BitstampData::into_update] with [DuplicatePrices::Keep].
+    fn from(data: BitstampData) -> Self {
+        data.into_update(DuplicatePrices::Keep)
+            .expect("Invalid update from Bitstamp")
+    }
+}
+
+#[cfg(test)]
 impl Into<InputUpdate> for BitstampInput {
     fn into(self) -> InputUpdate {
-        if let BitstampInput::Data {
-            data: BitstampData { asks, bids },
-        } = self
-        {
-            // We assume that asks and bids come sorted from Bitstamp,
-            // this call will panic in `debug` mode if that is not the case.
-            InputUpdate::new(Exchange::Bitstamp, asks.into(), bids.into())
+        if let BitstampInput::Data { data } = self {
+            data.into()
         } else {
             unreachable!("unhandled reconnect packet")
         }
     }
 }
 
+/// Deserializes a string with unix seconds as unix milliseconds.
+fn deserialize_seconds_as_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let seconds = String::deserialize(deserializer)?;
+    seconds
+        .parse::<u64>()
+        .ok()
+        .and_then(|seconds| seconds.checked_mul(1000))
+        .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&seconds), &"unix seconds"))
+}
+
+/// Age of the `timestamp` of Bitstamp data after which it is considered stale.
+pub const STALE_UPDATE_AGE: Duration = Duration::from_secs(30);
+
+/// Returns true if data generated at `timestamp_ms` is older than [STALE_UPDATE_AGE] at `now_ms`, both in unix milliseconds.
+fn is_stale(timestamp_ms: u64, now_ms: u64) -> bool {
+    now_ms.saturating_sub(timestamp_ms) > STALE_UPDATE_AGE.as_millis() as u64
+}
+
+/// Logs a warning if data generated at `timestamp_ms` is older than [STALE_UPDATE_AGE].
+fn warn_if_stale(timestamp_ms: u64) {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before the unix epoch")
+        .as_millis() as u64;
+    if is_stale(timestamp_ms, now_ms) {
+        eprintln!(
+            "Stale update from Bitstamp, generated {}ms ago",
+            now_ms - timestamp_ms
+        );
+    }
+}
+
+/// Channel prefix of the order book subscribed to by [get_stream], the channel is `{prefix}_{pair}`.
+pub const DEFAULT_CHANNEL_PREFIX: &str = "order_book";
+
+/// Returns the message which subscribes to the `{channel_prefix}_{pair}` channel.
+pub fn subscribe_message(channel_prefix: &str, pair: &str) -> String {
+    format!(
+        r#"{{"event":"bts:subscribe","data":{{"channel":"{}_{}"}}}}"#,
+        channel_prefix, pair
+    )
+}
+
+/// Url of the Bitstamp websocket API.
+const URL: &str = "wss://ws.bitstamp.net";
+
+/// Maximum number of messages buffered while waiting for the subscription to succeed.
+const ACK_BUFFER_SIZE: usize = 10;
+
+/// Default time Bitstamp has to acknowledge the subscription, see [SourceConfig::subscribe_timeout].
+pub const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Establishes a new connection to Bitstamp and returns a [Stream] of [BitstampInput].
+///
+/// The subscribe message is rate limited by [SourceConfig::throttle].
+/// Waits for `limiter` before connecting. Raw text frames are forwarded to [SourceConfig::raw_messages] before parsing.
 async fn get_stream_inner<B: Backoff>(
+    url: &Url,
     subscribe_message: String,
     // Backoff is not Clone.
     backoff: impl Fn() -> B,
-) -> impl Stream<Item = Result<BitstampInput, tungstenite::Error>> {
-    let url = Url::parse("wss://ws.bitstamp.net").unwrap();
-
+    config: &SourceConfig,
+    limiter: &ReconnectLimiter,
+) -> impl Stream<Item = Result<BitstampInput, SourceError>> {
+    limiter.wait().await;
     retry_notify(
         backoff(),
         || async {
-            let (mut socket, _) = connect_async(url.clone()).await?;
+            let mut socket = connect(url, config.connection_timeout).await?;
+            config.throttle.acquire().await;
             socket.send(subscribe_message.clone().into()).await?;
             Ok(socket)
         },
@@ -65,64 +179,339 @@ async fn get_stream_inner<B: Backoff>(
     )
     .await
     .expect("Could not open connection to Bitstamp")
-    .filter_map(|item| {
-        match item {
-            Ok(Message::Text(mut text)) => match simd_json::from_str::<BitstampInput>(&mut text) {
-                Ok(input) => Some(Ok(input)),
-                Err(err) => Some(Err(tungstenite::Error::Protocol(Cow::Owned(
-                    err.to_string(),
-                )))),
-            },
-            Ok(Message::Close(_)) => Some(Err(tungstenite::Error::ConnectionClosed)),
-            Err(err) => Some(Err(err)),
-            Ok(_) => {
-                // Ignore ping, pong and binary messages
-                None
-            }
+    .filter_map({
+        let raw_messages = config.raw_messages.clone();
+        move |item| {
+            forward_raw(raw_messages.as_ref(), &item);
+            parse_message(item)
         }
     })
 }
 
+/// Parses a websocket message from Bitstamp, returns [None] for messages that should be ignored.
+fn parse_message(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<BitstampInput, SourceError>> {
+    match item {
+        Ok(Message::Text(mut text)) => match simd_json::from_str::<BitstampInput>(&mut text) {
+            Ok(input) => Some(Ok(input)),
+            Err(err) => Some(Err(tungstenite::Error::Protocol(Cow::Owned(
+                err.to_string(),
+            ))
+            .into())),
+        },
+        Ok(Message::Close(frame)) => Some(Err(SourceError::Closed(close_action(frame.as_ref())))),
+        Err(err) => Some(Err(err.into())),
+        Ok(_) => {
+            // Ignore ping, pong and binary messages
+            None
+        }
+    }
+}
+
 /// Creates a new [InputUpdate] [Stream] from the provided `pair` by connecting to the Bitstamp [websocket API](https://www.bitstamp.net/websocket/v2/).
 /// The stream is resilient and will retry if errors happen. If the pair is not valid, the stream will be empty, this is because there is no signal
 // from Bitstamp that indicates a pair is not valid.
+/// Subscribe messages are rate limited by [SourceConfig::throttle] so reconnection storms don't exceed Bitstamp's limits.
 pub fn get_stream<B: Backoff>(
     pair: String,
     backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
 ) -> impl Stream<Item = InputUpdate> {
-    let subscribe_message = format!(
-        r#"
-        {{
-            "event": "bts:subscribe",
-            "data": {{
-                "channel": "order_book_{}"
-            }}
-        }}
-        "#,
-        pair
-    );
+    get_channel_stream(pair, DEFAULT_CHANNEL_PREFIX.to_string(), backoff, config)
+}
 
+/// Same as [get_stream] but subscribes to the `{channel_prefix}_{pair}` channel instead of [DEFAULT_CHANNEL_PREFIX].
+///
+/// The channel must send the same schema as `order_book`, for example `detail_order_book`, which adds an order id
+/// to every level and is also supported, only the best [TOP_LEVELS] are kept.
+pub fn get_channel_stream<B: Backoff>(
+    pair: String,
+    channel_prefix: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    let subscribe_message = subscribe_message(&channel_prefix, &Exchange::Bitstamp.symbol(&pair));
+    get_url_stream(
+        Url::parse(URL).expect("Invalid Bitstamp url"),
+        subscribe_message,
+        backoff,
+        config,
+    )
+}
+
+/// Same as [get_channel_stream] but connects to `url` and sends the provided `subscribe_message`.
+fn get_url_stream<B: Backoff>(
+    url: Url,
+    subscribe_message: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
     stream! {
+        let limiter = ReconnectLimiter::new(config.min_reconnect_interval);
         loop{
-            let mut s = get_stream_inner(subscribe_message.clone(),backoff.clone()).await;
+            let mut s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+            // Bitstamp only guarantees the order of the data after the subscription succeeds.
+            let mut pending = AckBuffer::new(ACK_BUFFER_SIZE)
+                .with_timeout(config.subscribe_timeout.unwrap_or(SUBSCRIBE_TIMEOUT));
+            let mut stall = StallDetector::new(config.stall_threshold);
 
-            while let Some(value) = s.next().await {
+            while let Some(value) = next_before(&mut s, pending.deadline()).await {
                 match value{
-                    Ok(value @BitstampInput::Data{..}) => yield value.into(),
+                    Ok(BitstampInput::Data{data}) => {
+                        config.record_message();
+                        warn_if_stale(data.timestamp_ms);
+                        let microtimestamp = data.microtimestamp.clone();
+                        let update = match data.into_update(config.duplicate_prices) {
+                            Ok(update) => update,
+                            Err(err) => {
+                                eprintln!("Invalid update from Bitstamp: {}, skipping", err);
+                                continue;
+                            }
+                        };
+                        let keyed = (microtimestamp, update);
+                        if stall.is_stalled(&keyed) {
+                            eprintln!("Bitstamp stream stalled, restarting");
+                            config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::Stalled);
+                            s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                            pending.reset();
+                            stall.reset();
+                        } else if let Some(update) = pending.push(keyed.1) {
+                            yield update;
+                        }
+                    }
                     Ok(BitstampInput::Reconnect)=>{
                         eprintln!("Reconnect request received from Bitstamp, reconnecting");
-                        s = get_stream_inner(subscribe_message.clone(),backoff.clone()).await;
+                        config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::Requested);
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        pending.reset();
+                        stall.reset();
+                    }
+                    Err(SourceError::AckTimeout) => {
+                        eprintln!("Bitstamp didn't acknowledge the subscription in time, reconnecting");
+                        config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::AckTimeout);
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        pending.reset();
+                        stall.reset();
+                    }
+                    Err(SourceError::Closed(CloseAction::Terminate)) => {
+                        eprintln!("Bitstamp closed the connection with an unrecoverable error, stopping");
+                        return;
+                    }
+                    Err(SourceError::Closed(action)) => {
+                        eprintln!("Bitstamp closed the connection, reconnecting");
+                        config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::Closed);
+                        if let CloseAction::Delay(delay) = action {
+                            sleep(delay).await;
+                        }
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        pending.reset();
+                        stall.reset();
                     }
                     Err(err)=>{
                         eprintln!("Unexpected error in Bitstamp stream: {}, restarting",err);
-                        s = get_stream_inner(subscribe_message.clone(),backoff.clone()).await;
+                        config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::Error);
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        pending.reset();
+                        stall.reset();
                     }
                     Ok(BitstampInput::SubSuccess) => {
-                        // Ignore successful connection message.
+                        for update in pending.ack() {
+                            yield update;
+                        }
                     }
                 }
             }
             eprintln!("Bitstamp stream stopped unexpectedly, restarting");
+            config.notify_reconnect(Exchange::Bitstamp, ReconnectReason::StreamEnded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BackoffConfig, MockWsServer, ReconnectEvent, TokenBucket};
+    use super::*;
+    use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+    #[test]
+    fn test_is_stale() {
+        assert!(!is_stale(1_000_000, 1_000_000));
+        assert!(!is_stale(1_000_000, 1_030_000));
+        assert!(is_stale(1_000_000, 1_030_001));
+        // From the future.
+        assert!(!is_stale(1_000_000, 0));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let parse = |timestamp: &str| {
+            parse_message(Ok(Message::Text(format!(
+                r#"{{"event":"data","channel":"order_book_ethbtc","data":{{"timestamp":"{}","microtimestamp":"1","bids":[["0.5","1"]],"asks":[["1","1"]]}}}}"#,
+                timestamp
+            ))))
+        };
+        match parse("1612345678") {
+            Some(Ok(input)) => assert_eq!(
+                Into::<InputUpdate>::into(input).exchange_timestamp_ms(),
+                Some(1_612_345_678_000)
+            ),
+            _ => panic!("Invalid data message"),
+        }
+        assert!(matches!(parse("-1"), Some(Err(SourceError::Ws(_)))));
+        assert!(matches!(parse("blah"), Some(Err(SourceError::Ws(_)))));
+        assert!(matches!(
+            parse("18446744073709551615"),
+            Some(Err(SourceError::Ws(_)))
+        ));
+    }
+
+    #[test]
+    fn test_detail_order_book() {
+        let parse = |levels: &str| -> InputUpdate {
+            match parse_message(Ok(Message::Text(format!(
+                r#"{{"event":"data","channel":"detail_order_book_ethbtc","data":{{"timestamp":"1","microtimestamp":"1","bids":[{}],"asks":[{}]}}}}"#,
+                levels, levels
+            )))) {
+                Some(Ok(input)) => input.into(),
+                _ => panic!("Invalid data message"),
+            }
+        };
+        assert_eq!(parse(r#"["1","2","1234"]"#), parse(r#"["1","2"]"#),);
+
+        assert_eq!(
+            subscribe_message("detail_order_book", "ethbtc"),
+            r#"{"event":"bts:subscribe","data":{"channel":"detail_order_book_ethbtc"}}"#
+        );
+    }
+
+    #[test]
+    fn test_from_data() {
+        let text = || {
+            r#"{"event":"data","channel":"order_book_ethbtc","data":{"timestamp":"2","microtimestamp":"2000001","bids":[["0.5","1"],["0.4","2"]],"asks":[["1","1"]]}}"#.to_string()
+        };
+        let data = match parse_message(Ok(Message::Text(text()))) {
+            Some(Ok(BitstampInput::Data { data })) => data,
+            _ => panic!("Invalid data message"),
+        };
+        let input = match parse_message(Ok(Message::Text(text()))) {
+            Some(Ok(input)) => input,
+            _ => panic!("Invalid data message"),
+        };
+        let update = InputUpdate::from(data);
+        assert_eq!(update, Into::<InputUpdate>::into(input));
+        assert_eq!(update.exchange_timestamp_ms(), Some(2000));
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert!(matches!(
+            parse_message(Ok(Message::Text(
+                r#"{"event":"data","channel":"order_book_ethbtc","data":{"timestamp":"1","microtimestamp":"1","bids":[["0.5","1"]],"asks":[["1","1"]]}}"#.to_string()
+            ))),
+            Some(Ok(BitstampInput::Data { .. }))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Text(
+                r#"{"event":"bts:request_reconnect","channel":"","data":""}"#.to_string()
+            ))),
+            Some(Ok(BitstampInput::Reconnect))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Text("{}".to_string()))),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(parse_message(Ok(Message::Pong(vec![]))).is_none());
+
+        assert!(matches!(
+            parse_message(Ok(Message::Close(None))),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Away,
+                reason: Cow::Borrowed("")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: Cow::Borrowed("")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Delay(_))))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Unsupported,
+                reason: Cow::Borrowed("")
+            })))),
+            Some(Err(SourceError::Closed(CloseAction::Terminate)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bitstamp_reconnect_on_close() {
+        let subscribed = || {
+            Message::Text(
+                r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#
+                    .to_string(),
+            )
+        };
+        let data = |price: u32| {
+            Message::Text(format!(
+                r#"{{"event":"data","channel":"order_book_ethbtc","data":{{"timestamp":"1","microtimestamp":"{0}","bids":[["0.5","1"]],"asks":[["{0}","1"]]}}}}"#,
+                price
+            ))
+        };
+        let server = MockWsServer::start(vec![
+            vec![
+                subscribed(),
+                data(1),
+                data(2),
+                Message::Close(Some(CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: Cow::Borrowed(""),
+                })),
+            ],
+            vec![subscribed(), data(3), data(4)],
+        ])
+        .await;
+
+        let (reconnect_tx, mut reconnect_rx) = tokio::sync::mpsc::channel(10);
+        let config = SourceConfig {
+            throttle: TokenBucket::new(2, Duration::from_secs(1)),
+            reconnect_notifier: Some(reconnect_tx),
+            min_reconnect_interval: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut stream = Box::pin(get_url_stream(
+            server.url(),
+            subscribe_message(DEFAULT_CHANNEL_PREFIX, "ethbtc"),
+            BackoffConfig::default().factory(),
+            config,
+        ));
+
+        let mut prices = Vec::new();
+        for _ in 0..4 {
+            let update = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .expect("Timed out waiting for an update")
+                .unwrap();
+            let (_, asks, _) = update.take();
+            prices.push(Into::<f64>::into(asks[0].price));
         }
+        assert_eq!(prices, vec![1., 2., 3., 4.]);
+
+        // Dropping the stream drops the notifier, so every event has been received.
+        drop(stream);
+        assert_eq!(
+            reconnect_rx.recv().await,
+            Some(ReconnectEvent {
+                exchange: Exchange::Bitstamp,
+                reason: ReconnectReason::Closed
+            })
+        );
+        assert_eq!(reconnect_rx.recv().await, None);
     }
 }
@@ -0,0 +1,801 @@
+//! Sources for the order book of Coinbase, from either of its public websocket APIs.
+//!
+//! [get_stream] uses the Exchange [websocket feed](https://docs.cdp.coinbase.com/exchange/docs/websocket-channels#level2-channel)
+//! `level2` channel, it connects to `wss://ws-feed.exchange.coinbase.com` and sends [subscribe_message]. Handled messages:
+//! - `snapshot`: replaces the local book, `{"type":"snapshot","product_id":"ETH-BTC","bids":[["0.05","1.5"]],"asks":[["0.051","2"]]}`.
+//! - `l2update`: `{"type":"l2update","product_id":"ETH-BTC","changes":[["buy","0.05","0"]],"time":"2019-08-14T20:42:27.265Z"}`.
+//! - `subscriptions`: acknowledges the subscription.
+//! - `error`: Coinbase rejected the subscription, for example because the product doesn't exist.
+//! - `heartbeat`: ignored.
+//!
+//! [get_advanced_trade_stream] uses the Advanced Trade [websocket API](https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels#level2-channel)
+//! `level2` channel, it connects to `wss://advanced-trade-ws.coinbase.com` and sends [advanced_trade_subscribe_message]. Handled messages:
+//! - `l2_data`: `{"channel":"l2_data","timestamp":"2023-02-09T20:32:50.714964855Z","sequence_num":0,"events":[{"type":"snapshot","product_id":"ETH-BTC","updates":[{"side":"bid","event_time":"1970-01-01T00:00:00Z","price_level":"0.05","new_quantity":"1.5"}]}]}`,
+//!   where each event is either a `snapshot` or an `update` and the side is either `bid` or `offer`.
+//! - `subscriptions`: acknowledges the subscription.
+//! - `error`: Coinbase rejected the subscription.
+//! - `heartbeats`: ignored.
+//!
+//! Both APIs send the book once and then only the levels which changed, [get_diff_stream] and [get_advanced_trade_diff_stream]
+//! emit them as [InputDiffs](InputDiff) while the other sources keep a [DiffBook](super::super::DiffBook) and emit its best [TOP_LEVELS](crate::TOP_LEVELS) after every message.
+//! Snapshots replace the book, changes with a size of `"0"` remove the level and the rest replace the level with the same price.
+//! Updates received before the snapshot of each connection are discarded.
+//! If the subscription isn't acknowledged within [SourceConfig::subscribe_timeout] the source reconnects with
+//! [ReconnectReason::AckTimeout], if it's rejected the stream ends.
+//!
+//! Ping, pong and binary frames are ignored, tungstenite answers pings on its own. Any other message fails to parse
+//! and reconnects with [ReconnectReason::Error] like connection errors do, close frames are handled according to [close_action]
+//! and, if [SourceConfig::stall_threshold] is set, a feed which repeats the same timestamp and levels reconnects
+//! with [ReconnectReason::Stalled]. The book is sent again on every reconnection.
+//!
+//! Connections are retried with the provided backoff, which panics if it gives up, and are made at most once every
+//! [SourceConfig::min_reconnect_interval]. The streams only end if Coinbase rejects the subscription or closes the connection
+//! with an unrecoverable error.
+use super::super::{
+    DeserializeLevelTuple, Exchange, FinitePositiveF64, InputDiff, InputUpdate, Level,
+};
+use super::{
+    close_action, connect, forward_raw, into_updates, kraken, next_before, CloseAction,
+    ReconnectLimiter, ReconnectReason, SourceConfig, SourceError, StallDetector,
+};
+use async_stream::stream;
+use backoff::{backoff::Backoff, tokio::retry_notify};
+use futures_util::SinkExt;
+use serde::Deserialize;
+use std::{borrow::Cow, time::Duration};
+use tokio::time::{sleep, Instant};
+use tokio_stream::{Stream, StreamExt};
+use tungstenite::Message;
+use url::Url;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+/// Represents websocket messages from the Coinbase Exchange feed.
+enum CoinbaseInput {
+    Snapshot {
+        asks: Vec<DeserializeLevelTuple>,
+        bids: Vec<DeserializeLevelTuple>,
+    },
+    L2update {
+        changes: Vec<CoinbaseChange>,
+        time: Option<String>,
+    },
+    Subscriptions,
+    Error {
+        message: String,
+        reason: Option<String>,
+    },
+    Heartbeat,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+/// Represents websocket messages from the Coinbase Advanced Trade API.
+enum AdvancedTradeInput {
+    Channel(AdvancedTradeChannel),
+    Error { message: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+/// Represents messages from an Advanced Trade channel.
+enum AdvancedTradeChannel {
+    L2Data {
+        timestamp: Option<String>,
+        events: Vec<AdvancedTradeEvent>,
+    },
+    Subscriptions,
+    Heartbeats,
+}
+
+#[derive(Deserialize)]
+/// Represents an event inside Advanced Trade `l2_data` messages.
+struct AdvancedTradeEvent {
+    #[serde(rename = "type")]
+    kind: CoinbaseBookKind,
+    updates: Vec<AdvancedTradeUpdate>,
+}
+
+#[derive(Deserialize)]
+/// Represents a level inside Advanced Trade `l2_data` events, a `new_quantity` of 0 removes the level.
+struct AdvancedTradeUpdate {
+    side: CoinbaseSide,
+    price_level: FinitePositiveF64,
+    new_quantity: FinitePositiveF64,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// Whether a book event contains the whole book or the levels which changed.
+enum CoinbaseBookKind {
+    Snapshot,
+    Update,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// Side of the book of a [CoinbaseChange], bids are `buy` in the Exchange feed and `bid` in Advanced Trade,
+/// asks are `sell` and `offer`.
+enum CoinbaseSide {
+    #[serde(alias = "bid")]
+    Buy,
+    #[serde(alias = "offer", alias = "ask")]
+    Sell,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+/// Represents a `[side, price, size]` change, a size of 0 removes the level.
+struct CoinbaseChange(CoinbaseSide, FinitePositiveF64, FinitePositiveF64);
+
+/// Messages from either Coinbase API, see [parse_message] and [parse_advanced_trade_message].
+enum CoinbaseMessage {
+    /// Events to apply to the book in order, with the timestamp of the message.
+    Book {
+        events: Vec<(CoinbaseBookKind, Vec<CoinbaseChange>)>,
+        timestamp: Option<String>,
+    },
+    Subscribed,
+    Rejected(String),
+}
+
+/// Url of the Coinbase Exchange websocket feed.
+const URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+/// Url of the Coinbase Advanced Trade websocket API.
+const ADVANCED_TRADE_URL: &str = "wss://advanced-trade-ws.coinbase.com";
+
+/// Default time Coinbase has to acknowledge the subscription, see [SourceConfig::subscribe_timeout].
+pub const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns `pair` as a Coinbase product id, for example `ethbtc` becomes `ETH-BTC`.
+///
+/// Pairs which already contain a `-` are only uppercased, otherwise the quote is recognized like [kraken::symbol] does.
+pub fn symbol(pair: &str) -> String {
+    if pair.contains('-') {
+        return pair.to_uppercase();
+    }
+    kraken::symbol(pair).replace('/', "-")
+}
+
+/// Returns the message which subscribes to the Exchange feed `level2` channel of `product_id`.
+pub fn subscribe_message(product_id: &str) -> String {
+    format!(
+        r#"{{"type":"subscribe","product_ids":["{}"],"channels":["level2"]}}"#,
+        product_id
+    )
+}
+
+/// Returns the message which subscribes to the Advanced Trade `level2` channel of `product_id`.
+pub fn advanced_trade_subscribe_message(product_id: &str) -> String {
+    format!(
+        r#"{{"type":"subscribe","product_ids":["{}"],"channel":"level2"}}"#,
+        product_id
+    )
+}
+
+/// Parser of the text frames of one of the Coinbase APIs, returns [None] for messages that should be ignored.
+type Parser = fn(String) -> Option<Result<CoinbaseMessage, SourceError>>;
+
+/// Establishes a new connection to Coinbase and returns a [Stream] of the messages parsed with `parse`.
+///
+/// The subscribe message is rate limited by [SourceConfig::throttle].
+/// Waits for `limiter` before connecting. Raw text frames are forwarded to [SourceConfig::raw_messages] before parsing.
+async fn get_stream_inner<B: Backoff>(
+    url: &Url,
+    subscribe_message: String,
+    parse: Parser,
+    // Backoff is not Clone.
+    backoff: impl Fn() -> B,
+    config: &SourceConfig,
+    limiter: &ReconnectLimiter,
+) -> impl Stream<Item = Result<CoinbaseMessage, SourceError>> {
+    limiter.wait().await;
+    retry_notify(
+        backoff(),
+        || async {
+            let mut socket = connect(url, config.connection_timeout).await?;
+            config.throttle.acquire().await;
+            socket.send(subscribe_message.clone().into()).await?;
+            Ok(socket)
+        },
+        |err, _| eprintln!("Error creating Coinbase connection: {}, retrying", err),
+    )
+    .await
+    .expect("Could not open connection to Coinbase")
+    .filter_map({
+        let raw_messages = config.raw_messages.clone();
+        move |item| {
+            forward_raw(raw_messages.as_ref(), &item);
+            parse_frame(item, parse)
+        }
+    })
+}
+
+/// Parses a websocket frame from Coinbase, text frames are parsed with `parse`.
+/// Returns [None] for frames that should be ignored.
+fn parse_frame(
+    item: Result<Message, tungstenite::Error>,
+    parse: Parser,
+) -> Option<Result<CoinbaseMessage, SourceError>> {
+    match item {
+        Ok(Message::Text(text)) => parse(text),
+        Ok(Message::Close(frame)) => Some(Err(SourceError::Closed(close_action(frame.as_ref())))),
+        Err(err) => Some(Err(err.into())),
+        Ok(_) => {
+            // Ignore ping, pong and binary messages
+            None
+        }
+    }
+}
+
+/// Returns a [SourceError::Ws] for a message which couldn't be parsed.
+fn parse_error(err: simd_json::Error) -> SourceError {
+    tungstenite::Error::Protocol(Cow::Owned(err.to_string())).into()
+}
+
+/// Parses a text message from the Coinbase Exchange feed.
+fn parse_message(mut text: String) -> Option<Result<CoinbaseMessage, SourceError>> {
+    let message = match simd_json::from_str::<CoinbaseInput>(&mut text) {
+        Ok(CoinbaseInput::Snapshot { asks, bids }) => {
+            let side = |side, levels: Vec<DeserializeLevelTuple>| {
+                levels.into_iter().map(move |level| {
+                    let Level { price, amount } = level.into();
+                    CoinbaseChange(side, price, amount)
+                })
+            };
+            let snapshot = side(CoinbaseSide::Sell, asks)
+                .chain(side(CoinbaseSide::Buy, bids))
+                .collect();
+            CoinbaseMessage::Book {
+                events: vec![(CoinbaseBookKind::Snapshot, snapshot)],
+                timestamp: None,
+            }
+        }
+        Ok(CoinbaseInput::L2update { changes, time }) => CoinbaseMessage::Book {
+            events: vec![(CoinbaseBookKind::Update, changes)],
+            timestamp: time,
+        },
+        Ok(CoinbaseInput::Subscriptions) => CoinbaseMessage::Subscribed,
+        Ok(CoinbaseInput::Error { message, reason }) => {
+            CoinbaseMessage::Rejected(format!("{} {}", message, reason.unwrap_or_default()))
+        }
+        Ok(CoinbaseInput::Heartbeat) => return None,
+        Err(err) => return Some(Err(parse_error(err))),
+    };
+    Some(Ok(message))
+}
+
+/// Parses a text message from the Coinbase Advanced Trade API.
+fn parse_advanced_trade_message(mut text: String) -> Option<Result<CoinbaseMessage, SourceError>> {
+    let message = match simd_json::from_str::<AdvancedTradeInput>(&mut text) {
+        Ok(AdvancedTradeInput::Channel(AdvancedTradeChannel::L2Data { timestamp, events })) => {
+            let events = events
+                .into_iter()
+                .map(|event| {
+                    let changes = event
+                        .updates
+                        .into_iter()
+                        .map(|update| {
+                            CoinbaseChange(update.side, update.price_level, update.new_quantity)
+                        })
+                        .collect();
+                    (event.kind, changes)
+                })
+                .collect();
+            CoinbaseMessage::Book { events, timestamp }
+        }
+        Ok(AdvancedTradeInput::Channel(AdvancedTradeChannel::Subscriptions)) => {
+            CoinbaseMessage::Subscribed
+        }
+        Ok(AdvancedTradeInput::Channel(AdvancedTradeChannel::Heartbeats)) => return None,
+        Ok(AdvancedTradeInput::Error { message }) => CoinbaseMessage::Rejected(message),
+        Err(err) => return Some(Err(parse_error(err))),
+    };
+    Some(Ok(message))
+}
+
+/// Returns an [InputDiff] for each of the `events` of a book message.
+fn into_diffs(events: Vec<(CoinbaseBookKind, Vec<CoinbaseChange>)>) -> Vec<InputDiff> {
+    events
+        .into_iter()
+        .map(|(kind, changes)| {
+            let (mut asks, mut bids) = (Vec::new(), Vec::new());
+            for CoinbaseChange(side, price, amount) in changes {
+                let level = Level { price, amount };
+                match side {
+                    CoinbaseSide::Buy => bids.push(level),
+                    CoinbaseSide::Sell => asks.push(level),
+                }
+            }
+            match kind {
+                CoinbaseBookKind::Snapshot => InputDiff::snapshot(Exchange::Coinbase, asks, bids),
+                CoinbaseBookKind::Update => InputDiff::new(Exchange::Coinbase, asks, bids),
+            }
+        })
+        .collect()
+}
+
+/// Creates a new [InputUpdate] [Stream] from the provided `pair` by connecting to the Coinbase Exchange [websocket feed](https://docs.cdp.coinbase.com/exchange/docs/websocket-channels#level2-channel),
+/// see [symbol] for the accepted pairs.
+/// The stream is resilient and will retry if errors happen. If the pair is not valid, the stream ends.
+/// Subscribe messages are rate limited by [SourceConfig::throttle] so reconnection storms don't exceed Coinbase's limits.
+pub fn get_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    into_updates(Exchange::Coinbase, get_diff_stream(pair, backoff, config))
+}
+
+/// Same as [get_stream] but emits the [InputDiffs](InputDiff) sent by Coinbase instead of the top of the book,
+/// to be merged with [MergeState::apply_diff](crate::merge::MergeState::apply_diff).
+///
+/// After every reconnection the next diff is a snapshot.
+pub fn get_diff_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputDiff> {
+    get_url_stream(
+        Url::parse(URL).expect("Invalid Coinbase url"),
+        subscribe_message(&Exchange::Coinbase.symbol(&pair)),
+        parse_message,
+        backoff,
+        config,
+    )
+}
+
+/// Same as [get_stream] but connects to the Coinbase Advanced Trade [websocket API](https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels#level2-channel).
+/// The updates are from [Exchange::Coinbase] too, only one of both streams should be merged.
+pub fn get_advanced_trade_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    into_updates(
+        Exchange::Coinbase,
+        get_advanced_trade_diff_stream(pair, backoff, config),
+    )
+}
+
+/// Same as [get_diff_stream] but connects to the Coinbase Advanced Trade websocket API, see [get_advanced_trade_stream].
+pub fn get_advanced_trade_diff_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputDiff> {
+    get_url_stream(
+        Url::parse(ADVANCED_TRADE_URL).expect("Invalid Coinbase Advanced Trade url"),
+        advanced_trade_subscribe_message(&Exchange::Coinbase.symbol(&pair)),
+        parse_advanced_trade_message,
+        backoff,
+        config,
+    )
+}
+
+/// Same as [get_diff_stream] but connects to `url`, sends the provided `subscribe_message` and parses the messages with `parse`.
+fn get_url_stream<B: Backoff>(
+    url: Url,
+    subscribe_message: String,
+    parse: Parser,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputDiff> {
+    let subscribe_timeout = config.subscribe_timeout.unwrap_or(SUBSCRIBE_TIMEOUT);
+    stream! {
+        let limiter = ReconnectLimiter::new(config.min_reconnect_interval);
+        loop{
+            let mut s = get_stream_inner(&url,subscribe_message.clone(),parse,backoff.clone(),&config,&limiter).await;
+            let mut ack_deadline = Some(Instant::now() + subscribe_timeout);
+            let mut has_snapshot = false;
+            let mut stall = StallDetector::new(config.stall_threshold);
+
+            while let Some(value) = next_before(&mut s, ack_deadline).await {
+                match value{
+                    Ok(CoinbaseMessage::Book{events, timestamp}) => {
+                        config.record_message();
+                        if events.iter().any(|(kind, _)| *kind == CoinbaseBookKind::Snapshot) {
+                            stall.reset();
+                        }
+                        let mut diffs = into_diffs(events);
+                        // Updates are only valid after a snapshot of the same connection.
+                        let first_snapshot = diffs.iter().position(InputDiff::is_snapshot);
+                        if !has_snapshot {
+                            match first_snapshot {
+                                Some(first_snapshot) => {
+                                    diffs.drain(..first_snapshot);
+                                    has_snapshot = true;
+                                }
+                                None => {
+                                    eprintln!("Coinbase update received before the snapshot, discarding");
+                                    continue;
+                                }
+                            }
+                        }
+                        let keyed = (timestamp, diffs);
+                        if stall.is_stalled(&keyed) {
+                            eprintln!("Coinbase stream stalled, restarting");
+                            config.notify_reconnect(Exchange::Coinbase, ReconnectReason::Stalled);
+                            s = get_stream_inner(&url,subscribe_message.clone(),parse,backoff.clone(),&config,&limiter).await;
+                            ack_deadline = Some(Instant::now() + subscribe_timeout);
+                            has_snapshot = false;
+                            stall.reset();
+                        } else {
+                            for diff in keyed.1 {
+                                yield diff;
+                            }
+                        }
+                    }
+                    Ok(CoinbaseMessage::Subscribed) => {
+                        ack_deadline = None;
+                    }
+                    Ok(CoinbaseMessage::Rejected(message)) => {
+                        eprintln!("Coinbase rejected the subscription: {}, stopping", message);
+                        return;
+                    }
+                    Err(SourceError::AckTimeout) => {
+                        eprintln!("Coinbase didn't acknowledge the subscription in time, reconnecting");
+                        config.notify_reconnect(Exchange::Coinbase, ReconnectReason::AckTimeout);
+                        s = get_stream_inner(&url,subscribe_message.clone(),parse,backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        has_snapshot = false;
+                        stall.reset();
+                    }
+                    Err(SourceError::Closed(CloseAction::Terminate)) => {
+                        eprintln!("Coinbase closed the connection with an unrecoverable error, stopping");
+                        return;
+                    }
+                    Err(SourceError::Closed(action)) => {
+                        eprintln!("Coinbase closed the connection, reconnecting");
+                        config.notify_reconnect(Exchange::Coinbase, ReconnectReason::Closed);
+                        if let CloseAction::Delay(delay) = action {
+                            sleep(delay).await;
+                        }
+                        s = get_stream_inner(&url,subscribe_message.clone(),parse,backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        has_snapshot = false;
+                        stall.reset();
+                    }
+                    Err(err)=>{
+                        eprintln!("Unexpected error in Coinbase stream: {}, restarting",err);
+                        config.notify_reconnect(Exchange::Coinbase, ReconnectReason::Error);
+                        s = get_stream_inner(&url,subscribe_message.clone(),parse,backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        has_snapshot = false;
+                        stall.reset();
+                    }
+                }
+            }
+            eprintln!("Coinbase stream stopped unexpectedly, restarting");
+            config.notify_reconnect(Exchange::Coinbase, ReconnectReason::StreamEnded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BackoffConfig, MockWsServer, TokenBucket};
+    use super::*;
+    use crate::{arrayvec, input::DiffBook};
+    use std::convert::TryInto;
+
+    fn snapshot(bids: &str, asks: &str) -> String {
+        format!(
+            r#"{{"type":"snapshot","product_id":"ETH-BTC","bids":[{}],"asks":[{}]}}"#,
+            bids, asks
+        )
+    }
+
+    fn l2update(changes: &str) -> String {
+        format!(
+            r#"{{"type":"l2update","product_id":"ETH-BTC","changes":[{}],"time":"2019-08-14T20:42:27.265Z"}}"#,
+            changes
+        )
+    }
+
+    fn l2_data(kind: &str, updates: &[(&str, &str, &str)]) -> String {
+        let updates = updates
+            .iter()
+            .map(|(side, price, quantity)| {
+                format!(
+                    r#"{{"side":"{}","event_time":"2023-02-09T20:32:50.714964855Z","price_level":"{}","new_quantity":"{}"}}"#,
+                    side, price, quantity
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{"channel":"l2_data","client_id":"","timestamp":"2023-02-09T20:32:50.714964855Z","sequence_num":0,"events":[{{"type":"{}","product_id":"ETH-BTC","updates":[{}]}}]}}"#,
+            kind, updates
+        )
+    }
+
+    fn events(
+        message: Option<Result<CoinbaseMessage, SourceError>>,
+    ) -> Vec<(CoinbaseBookKind, Vec<CoinbaseChange>)> {
+        match message {
+            Some(Ok(CoinbaseMessage::Book { events, .. })) => events,
+            _ => panic!("Invalid book message"),
+        }
+    }
+
+    #[test]
+    fn test_symbol() {
+        assert_eq!(symbol("ethbtc"), "ETH-BTC");
+        assert_eq!(symbol("btcusd"), "BTC-USD");
+        assert_eq!(symbol("eth-btc"), "ETH-BTC");
+        assert_eq!(
+            subscribe_message("ETH-BTC"),
+            r#"{"type":"subscribe","product_ids":["ETH-BTC"],"channels":["level2"]}"#
+        );
+        assert_eq!(
+            advanced_trade_subscribe_message("ETH-BTC"),
+            r#"{"type":"subscribe","product_ids":["ETH-BTC"],"channel":"level2"}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let price = |price: f64| TryInto::<FinitePositiveF64>::try_into(price).unwrap();
+        assert_eq!(
+            events(parse_message(snapshot(
+                r#"["0.05","1.5"]"#,
+                r#"["0.051","2"]"#
+            ))),
+            vec![(
+                CoinbaseBookKind::Snapshot,
+                vec![
+                    CoinbaseChange(CoinbaseSide::Sell, price(0.051), price(2.)),
+                    CoinbaseChange(CoinbaseSide::Buy, price(0.05), price(1.5)),
+                ]
+            )]
+        );
+        assert!(matches!(
+            parse_message(l2update(r#"["buy","0.05","0"],["sell","0.051","1"]"#)),
+            Some(Ok(CoinbaseMessage::Book { events, timestamp: Some(_) }))
+                if events[0].0 == CoinbaseBookKind::Update && events[0].1.len() == 2
+        ));
+        assert!(matches!(
+            parse_message(
+                r#"{"type":"subscriptions","channels":[{"name":"level2","product_ids":["ETH-BTC"]}]}"#
+                    .to_string()
+            ),
+            Some(Ok(CoinbaseMessage::Subscribed))
+        ));
+        assert!(matches!(
+            parse_message(
+                r#"{"type":"error","message":"Failed to subscribe","reason":"ETH-XYZ is not a valid product"}"#
+                    .to_string()
+            ),
+            Some(Ok(CoinbaseMessage::Rejected(_)))
+        ));
+
+        // Heartbeats are consumed silently.
+        assert!(parse_message(
+            r#"{"type":"heartbeat","last_trade_id":1,"product_id":"ETH-BTC","sequence":1,"time":"2019-08-14T20:42:27.265Z"}"#
+                .to_string()
+        )
+        .is_none());
+        assert!(parse_frame(Ok(Message::Ping(vec![])), parse_message).is_none());
+
+        assert!(matches!(
+            parse_message(l2update(r#"["buy","-0.05","1"]"#)),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse_message(l2update(r#"["hold","0.05","1"]"#)),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse_message(r#"{"type":"ticker"}"#.to_string()),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse_frame(Ok(Message::Close(None)), parse_message),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+    }
+
+    #[test]
+    fn test_parse_advanced_trade_message() {
+        let price = |price: f64| TryInto::<FinitePositiveF64>::try_into(price).unwrap();
+        assert_eq!(
+            events(parse_advanced_trade_message(l2_data(
+                "snapshot",
+                &[("bid", "0.05", "1.5"), ("offer", "0.051", "2")]
+            ))),
+            vec![(
+                CoinbaseBookKind::Snapshot,
+                vec![
+                    CoinbaseChange(CoinbaseSide::Buy, price(0.05), price(1.5)),
+                    CoinbaseChange(CoinbaseSide::Sell, price(0.051), price(2.)),
+                ]
+            )]
+        );
+        assert_eq!(
+            events(parse_advanced_trade_message(l2_data(
+                "update",
+                &[("ask", "0.051", "0")]
+            ))),
+            vec![(
+                CoinbaseBookKind::Update,
+                vec![CoinbaseChange(CoinbaseSide::Sell, price(0.051), price(0.))]
+            )]
+        );
+        assert!(matches!(
+            parse_advanced_trade_message(
+                r#"{"channel":"subscriptions","client_id":"","timestamp":"2023-02-09T20:32:50.714964855Z","sequence_num":1,"events":[{"subscriptions":{"level2":["ETH-BTC"]}}]}"#
+                    .to_string()
+            ),
+            Some(Ok(CoinbaseMessage::Subscribed))
+        ));
+        assert!(matches!(
+            parse_advanced_trade_message(
+                r#"{"type":"error","message":"failure to subscribe"}"#.to_string()
+            ),
+            Some(Ok(CoinbaseMessage::Rejected(message))) if message == "failure to subscribe"
+        ));
+
+        // Heartbeats are consumed silently.
+        assert!(parse_advanced_trade_message(
+            r#"{"channel":"heartbeats","client_id":"","timestamp":"2023-06-23T20:31:26.122969572Z","sequence_num":0,"events":[{"current_time":"2023-06-23 20:31:56.121961769 +0000 UTC m=+91717.525857105","heartbeat_counter":"3049"}]}"#
+                .to_string()
+        )
+        .is_none());
+
+        assert!(matches!(
+            parse_advanced_trade_message(l2_data("update", &[("bid", "-0.05", "1")])),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse_advanced_trade_message(r#"{"channel":"ticker","events":[]}"#.to_string()),
+            Some(Err(SourceError::Ws(_)))
+        ));
+    }
+
+    #[test]
+    fn test_into_diffs() {
+        let snapshot = into_diffs(events(parse_message(snapshot(
+            r#"["0.5","1"],["0.3","0"]"#,
+            r#"["2","1"],["1","1"]"#,
+        ))));
+        assert_eq!(
+            snapshot,
+            vec![InputDiff::snapshot(
+                Exchange::Coinbase,
+                vec![lvl!(2., 1.), lvl!(1., 1.)],
+                vec![lvl!(0.5, 1.), lvl!(0.3, 0.)],
+            )]
+        );
+
+        let update = into_diffs(events(parse_message(l2update(
+            r#"["buy","0.5","0"],["sell","2","3"],["sell","1.5","1"]"#,
+        ))));
+        assert_eq!(
+            update,
+            vec![InputDiff::new(
+                Exchange::Coinbase,
+                vec![lvl!(2., 3.), lvl!(1.5, 1.)],
+                vec![lvl!(0.5, 0.)],
+            )]
+        );
+
+        // Advanced Trade events are applied to the same book.
+        let advanced_trade = into_diffs(events(parse_advanced_trade_message(l2_data(
+            "update",
+            &[("offer", "1", "0"), ("bid", "0.45", "1")],
+        ))));
+        assert_eq!(
+            advanced_trade,
+            vec![InputDiff::new(
+                Exchange::Coinbase,
+                vec![lvl!(1., 0.)],
+                vec![lvl!(0.45, 1.)],
+            )]
+        );
+
+        let mut book = DiffBook::new();
+        for diff in snapshot.iter().chain(&update).chain(&advanced_trade) {
+            assert!(book.apply(diff));
+        }
+        assert_eq!(
+            book.to_update(Exchange::Coinbase),
+            InputUpdate::new(
+                Exchange::Coinbase,
+                arrayvec![lvl!(1.5, 1.), lvl!(2., 3.)],
+                arrayvec![lvl!(0.45, 1.)],
+            )
+        );
+    }
+
+    async fn next_ask_prices(
+        stream: impl Stream<Item = InputUpdate>,
+        count: usize,
+    ) -> Vec<Vec<f64>> {
+        tokio::pin!(stream);
+        let mut prices = Vec::new();
+        for _ in 0..count {
+            let update = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .expect("Timed out waiting for an update")
+                .unwrap();
+            let (_, asks, _) = update.take();
+            prices.push(
+                asks.iter()
+                    .map(|level| Into::<f64>::into(level.price))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        prices
+    }
+
+    fn config() -> SourceConfig {
+        SourceConfig {
+            throttle: TokenBucket::new(2, Duration::from_secs(1)),
+            min_reconnect_interval: Duration::from_secs(0),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_reconnect_on_close() {
+        let subscribed = || {
+            Message::Text(
+                r#"{"type":"subscriptions","channels":[{"name":"level2","product_ids":["ETH-BTC"]}]}"#
+                    .to_string(),
+            )
+        };
+        let server = MockWsServer::start(vec![
+            vec![
+                subscribed(),
+                Message::Text(snapshot(r#"["0.5","1"]"#, r#"["1","1"]"#)),
+                Message::Close(None),
+            ],
+            vec![
+                subscribed(),
+                // Discarded, the book is rebuilt from the next snapshot.
+                Message::Text(l2update(r#"["sell","3","1"]"#)),
+                Message::Text(snapshot(r#"["0.5","1"]"#, r#"["2","1"]"#)),
+            ],
+        ])
+        .await;
+
+        let stream = into_updates(
+            Exchange::Coinbase,
+            get_url_stream(
+                server.url(),
+                subscribe_message("ETH-BTC"),
+                parse_message,
+                BackoffConfig::default().factory(),
+                config(),
+            ),
+        );
+        assert_eq!(next_ask_prices(stream, 2).await, vec![vec![1.], vec![2.]]);
+    }
+
+    #[tokio::test]
+    async fn test_advanced_trade_stream() {
+        let server = MockWsServer::start(vec![vec![
+            Message::Text(
+                r#"{"channel":"subscriptions","events":[{"subscriptions":{"level2":["ETH-BTC"]}}]}"#
+                    .to_string(),
+            ),
+            Message::Text(l2_data("update", &[("offer", "3", "1")])),
+            Message::Text(l2_data("snapshot", &[("bid", "0.5", "1"), ("offer", "1", "1")])),
+            Message::Text(l2_data("update", &[("offer", "1", "0"), ("offer", "2", "1")])),
+        ]])
+        .await;
+
+        let stream = into_updates(
+            Exchange::Coinbase,
+            get_url_stream(
+                server.url(),
+                advanced_trade_subscribe_message("ETH-BTC"),
+                parse_advanced_trade_message,
+                BackoffConfig::default().factory(),
+                config(),
+            ),
+        );
+        assert_eq!(next_ask_prices(stream, 2).await, vec![vec![1.], vec![2.]]);
+    }
+}
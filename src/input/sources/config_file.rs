@@ -0,0 +1,247 @@
+use super::{binance, bitstamp, coinbase, kraken, BackoffConfig, SourceConfig};
+use crate::input::{Exchange, InputDiff, InputUpdate};
+use parse_display::Display;
+use serde::Deserialize;
+use std::{path::Path, pin::Pin, time::Duration};
+use tokio_stream::{Stream, StreamExt};
+
+/// Boxed source returned by [sources_from_config].
+pub type BoxedSource = Pin<Box<dyn Stream<Item = InputUpdate> + Send>>;
+
+/// Boxed source returned by [diff_source].
+pub type BoxedDiffSource = Pin<Box<dyn Stream<Item = InputDiff> + Send>>;
+
+#[derive(Debug, Display, PartialEq, Clone)]
+/// Errors returned by [sources_from_config].
+pub enum ConfigError {
+    #[display("Can't read config file: {0}")]
+    Io(String),
+    #[display("Invalid config file: {0}")]
+    Parse(String),
+    #[display("Unknown source type: {0}")]
+    UnknownExchange(String),
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+/// Contents of a sources config file.
+struct SourcesFile {
+    #[serde(default)]
+    backoff: BackoffFile,
+    #[serde(default)]
+    source: Vec<SourceFile>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+/// A `[[source]]` entry.
+struct SourceFile {
+    #[serde(rename = "type")]
+    exchange: String,
+    pair: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Default)]
+/// The `[backoff]` table, missing fields use the values of [BackoffConfig::default].
+struct BackoffFile {
+    initial_interval_ms: Option<u64>,
+    max_interval_ms: Option<u64>,
+    multiplier: Option<f64>,
+    /// 0 retries forever.
+    max_elapsed_secs: Option<u64>,
+}
+
+impl From<BackoffFile> for BackoffConfig {
+    fn from(file: BackoffFile) -> Self {
+        let default = BackoffConfig::default();
+        Self {
+            initial_interval: file
+                .initial_interval_ms
+                .map_or(default.initial_interval, Duration::from_millis),
+            max_interval: file
+                .max_interval_ms
+                .map_or(default.max_interval, Duration::from_millis),
+            multiplier: file.multiplier.unwrap_or(default.multiplier),
+            max_elapsed: match file.max_elapsed_secs {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => default.max_elapsed,
+            },
+        }
+    }
+}
+
+/// Returns a source for every `[[source]]` in the TOML file at `path`, for example:
+/// ```toml
+/// [backoff]
+/// initial_interval_ms = 500
+/// max_interval_ms = 60000
+/// multiplier = 1.5
+/// # 0 retries forever.
+/// max_elapsed_secs = 900
+///
+/// [[source]]
+/// type = "binance"
+/// pair = "ethbtc"
+///
+/// [[source]]
+/// type = "bitstamp"
+/// pair = "ethbtc"
+/// ```
+/// `type` is the [Exchange] name. All the sources share the backoff and use [SourceConfig::default],
+/// missing `[backoff]` fields use the values of [BackoffConfig::default].
+///
+/// The sources are boxed with [Box::pin] so they are [Unpin] and can be polled with [select!](tokio::select)
+/// or merged with [StreamExt::merge](tokio_stream::StreamExt::merge).
+pub fn sources_from_config(path: &Path) -> Result<Vec<BoxedSource>, ConfigError> {
+    let config = std::fs::read_to_string(path).map_err(|err| ConfigError::Io(err.to_string()))?;
+    sources_from_str(&config)
+}
+
+/// Returns the [get_stream](binance::get_stream) source of `exchange` for `pair` with [SourceConfig::default],
+/// boxed like the sources returned by [sources_from_config].
+pub fn source(exchange: Exchange, pair: String, backoff: &BackoffConfig) -> BoxedSource {
+    match exchange {
+        Exchange::Binance => Box::pin(binance::get_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+        Exchange::Bitstamp => Box::pin(bitstamp::get_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+        Exchange::Kraken => Box::pin(kraken::get_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+        Exchange::Coinbase => Box::pin(coinbase::get_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+    }
+}
+
+/// Same as [source] but emits [InputDiffs](InputDiff), the exchanges which send diffs, like [kraken::get_diff_stream],
+/// aren't limited to [TOP_LEVELS](crate::TOP_LEVELS), the updates of the rest are converted to snapshots.
+pub fn diff_source(exchange: Exchange, pair: String, backoff: &BackoffConfig) -> BoxedDiffSource {
+    match exchange {
+        Exchange::Coinbase => Box::pin(coinbase::get_diff_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+        Exchange::Kraken => Box::pin(kraken::get_diff_stream(
+            pair,
+            backoff.factory(),
+            SourceConfig::default(),
+        )),
+        _ => Box::pin(source(exchange, pair, backoff).map(InputDiff::from)),
+    }
+}
+
+/// Same as [sources_from_config] with the contents of the file.
+fn sources_from_str(config: &str) -> Result<Vec<BoxedSource>, ConfigError> {
+    let SourcesFile {
+        backoff,
+        source: sources,
+    } = toml::from_str(config).map_err(|err| ConfigError::Parse(err.to_string()))?;
+    let backoff = BackoffConfig::from(backoff);
+
+    sources
+        .into_iter()
+        .map(|SourceFile { exchange, pair }| {
+            let exchange = exchange
+                .parse()
+                .map_err(|_| ConfigError::UnknownExchange(exchange))?;
+            Ok(source(exchange, pair, &backoff))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let file: SourcesFile = toml::from_str(
+            r#"
+            [backoff]
+            initial_interval_ms = 100
+            max_elapsed_secs = 0
+
+            [[source]]
+            type = "binance"
+            pair = "ethbtc"
+
+            [[source]]
+            type = "bitstamp"
+            pair = "btcusd"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.source,
+            vec![
+                SourceFile {
+                    exchange: "binance".to_string(),
+                    pair: "ethbtc".to_string()
+                },
+                SourceFile {
+                    exchange: "bitstamp".to_string(),
+                    pair: "btcusd".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            BackoffConfig::from(file.backoff),
+            BackoffConfig {
+                initial_interval: Duration::from_millis(100),
+                max_elapsed: None,
+                ..BackoffConfig::default()
+            }
+        );
+        assert_eq!(
+            BackoffConfig::from(BackoffFile::default()),
+            BackoffConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_sources_from_str() {
+        // Sources don't connect until they are polled.
+        let sources = sources_from_str(
+            r#"
+            [[source]]
+            type = "binance"
+            pair = "ethbtc"
+
+            [[source]]
+            type = "bitstamp"
+            pair = "ethbtc"
+
+            [[source]]
+            type = "kraken"
+            pair = "ethbtc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources_from_str("").unwrap().len(), 0);
+
+        assert!(matches!(
+            sources_from_str("[[source]]\ntype = \"ftx\"\npair = \"ethbtc\""),
+            Err(ConfigError::UnknownExchange(exchange)) if exchange == "ftx"
+        ));
+        assert!(matches!(
+            sources_from_str("[[source]]\ntype = \"binance\""),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            sources_from_config(Path::new("/nonexistent/sources.toml")),
+            Err(ConfigError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+use super::super::{FinitePositiveF64, Level};
+use crate::TOP_LEVELS;
+use arrayvec::ArrayVec;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How levels with the same price on the same side of an update from a single exchange are handled,
+/// see [SourceConfig::duplicate_prices](super::SourceConfig::duplicate_prices).
+///
+/// Levels are assumed to be sorted, so levels with the same price are adjacent.
+pub enum DuplicatePrices {
+    /// Levels are passed through unchanged, for exchanges which [have unique prices](super::super::Exchange::has_unique_prices)
+    /// updates with repeated prices are logged and skipped.
+    Keep,
+    /// Levels with the same price are replaced by a single level with the sum of their amounts.
+    Sum,
+    /// Only the level with the largest amount of each price is kept.
+    Largest,
+}
+
+impl DuplicatePrices {
+    /// Returns `levels` with repeated prices handled according to `self`.
+    pub fn collapse(self, levels: ArrayVec<[Level; TOP_LEVELS]>) -> ArrayVec<[Level; TOP_LEVELS]> {
+        if self == DuplicatePrices::Keep {
+            return levels;
+        }
+        let mut collapsed = ArrayVec::<[Level; TOP_LEVELS]>::new();
+        for level in levels {
+            match collapsed.last_mut() {
+                Some(last) if last.price == level.price => {
+                    last.amount = match self {
+                        DuplicatePrices::Sum => {
+                            let amounts = [last.amount, level.amount];
+                            amounts.iter().sum::<FinitePositiveF64>()
+                        }
+                        _ => last.amount.max(level.amount),
+                    }
+                }
+                _ => collapsed.push(level),
+            }
+        }
+        collapsed
+    }
+}
+
+impl Default for DuplicatePrices {
+    /// [DuplicatePrices::Largest], so snapshots which repeat a price are still merged.
+    fn default() -> Self {
+        DuplicatePrices::Largest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arrayvec;
+
+    #[test]
+    fn test_collapse() {
+        let levels = || {
+            arrayvec![
+                lvl!(1., 1.),
+                lvl!(2., 1.),
+                lvl!(2., 3.),
+                lvl!(2., 2.),
+                lvl!(3., 1.)
+            ]
+        };
+        assert_eq!(DuplicatePrices::Keep.collapse(levels()), levels());
+        assert_eq!(
+            DuplicatePrices::Sum.collapse(levels()),
+            arrayvec![lvl!(1., 1.), lvl!(2., 6.), lvl!(3., 1.)]
+        );
+        assert_eq!(
+            DuplicatePrices::Largest.collapse(levels()),
+            arrayvec![lvl!(1., 1.), lvl!(2., 3.), lvl!(3., 1.)]
+        );
+        assert_eq!(DuplicatePrices::Sum.collapse(arrayvec![]), arrayvec![]);
+    }
+}
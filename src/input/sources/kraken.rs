@@ -0,0 +1,563 @@
+//! Source for the Kraken [websocket API v2](https://docs.kraken.com/api/docs/websocket-v2/book) `book` channel.
+//!
+//! Connects to `wss://ws.kraken.com/v2` and sends [subscribe_message] for the symbol returned by [symbol].
+//! Unlike the other exchanges Kraken sends the book once and then only the levels which changed, [get_diff_stream]
+//! emits them as [InputDiffs](InputDiff) while [get_stream] keeps a [DiffBook] and emits its best [TOP_LEVELS](crate::TOP_LEVELS)
+//! after every message. Handled messages:
+//! - `book` `snapshot`: replaces the local book,
+//!   `{"channel":"book","type":"snapshot","data":[{"symbol":"ETH/BTC","bids":[{"price":0.05,"qty":1.5}],"asks":[{"price":0.051,"qty":2}],"checksum":1}]}`,
+//!   with prices and amounts as numbers.
+//! - `book` `update`: same format, levels with a `qty` of 0 are removed and the rest replace the level with the same price,
+//!   then the book is truncated to [DEFAULT_DEPTH] levels per side as Kraken expects and the levels dropped are added to the diff as removals.
+//!   Updates received before a snapshot are discarded.
+//! - The `subscribe` response: if the subscription is rejected, for example because the symbol doesn't exist, the stream ends.
+//!   If it doesn't arrive within [SourceConfig::subscribe_timeout] the source reconnects with [ReconnectReason::AckTimeout].
+//! - `heartbeat` and `status`: ignored.
+//!
+//! Ping, pong and binary frames are ignored, tungstenite answers pings on its own. Any other message fails to parse
+//! and reconnects with [ReconnectReason::Error] like connection errors do, close frames are handled according to [close_action]
+//! and, if [SourceConfig::stall_threshold] is set, a feed which repeats the same `timestamp` and levels reconnects
+//! with [ReconnectReason::Stalled]. The local book is dropped on every reconnection, the next diff is a snapshot.
+//!
+//! Connections are retried with the provided backoff, which panics if it gives up, and are made at most once every
+//! [SourceConfig::min_reconnect_interval]. The stream only ends if Kraken rejects the subscription or closes the connection
+//! with an unrecoverable error.
+use super::super::{DiffBook, Exchange, FinitePositiveF64, InputDiff, InputUpdate, Level};
+use super::{
+    close_action, connect, forward_raw, into_updates, next_before, CloseAction, ReconnectLimiter,
+    ReconnectReason, SourceConfig, SourceError, StallDetector,
+};
+use async_stream::stream;
+use backoff::{backoff::Backoff, tokio::retry_notify};
+use futures_util::SinkExt;
+use serde::{de, Deserialize, Deserializer};
+use std::{borrow::Cow, convert::TryInto, time::Duration};
+use tokio::time::{sleep, Instant};
+use tokio_stream::{Stream, StreamExt};
+use tungstenite::Message;
+use url::Url;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+/// Represents websocket messages from Kraken.
+enum KrakenInput {
+    Channel(KrakenChannel),
+    Subscribed(KrakenSubscribed),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "channel", rename_all = "lowercase")]
+/// Represents messages from a Kraken channel.
+enum KrakenChannel {
+    Book {
+        #[serde(rename = "type")]
+        kind: KrakenBookKind,
+        data: Vec<KrakenBookData>,
+    },
+    Heartbeat,
+    Status,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// Whether a `book` message contains the whole book or the levels which changed.
+enum KrakenBookKind {
+    Snapshot,
+    Update,
+}
+
+#[derive(Deserialize)]
+/// Represents the book of a symbol inside Kraken `book` messages.
+struct KrakenBookData {
+    asks: Vec<KrakenLevel>,
+    bids: Vec<KrakenLevel>,
+    /// Only present in updates.
+    timestamp: Option<String>,
+}
+
+#[derive(Deserialize)]
+/// Represents a level inside Kraken `book` messages, a `qty` of 0 removes the level.
+struct KrakenLevel {
+    #[serde(deserialize_with = "deserialize_number")]
+    price: FinitePositiveF64,
+    #[serde(deserialize_with = "deserialize_number")]
+    qty: FinitePositiveF64,
+}
+
+#[derive(Deserialize)]
+/// Represents the response to the subscribe message.
+struct KrakenSubscribed {
+    success: bool,
+    error: Option<String>,
+}
+
+/// Deserializes a number as a [FinitePositiveF64], Kraken doesn't encode numbers as strings like the other exchanges.
+fn deserialize_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<FinitePositiveF64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    value
+        .try_into()
+        .map_err(|_| de::Error::invalid_value(de::Unexpected::Float(value), &"FinitePositiveF64"))
+}
+
+/// Url of the Kraken websocket API.
+const URL: &str = "wss://ws.kraken.com/v2";
+
+/// Number of levels per side subscribed to, Kraken only accepts 10, 25, 100, 500 and 1000.
+pub const DEFAULT_DEPTH: usize = 10;
+
+/// Default time Kraken has to acknowledge the subscription, see [SourceConfig::subscribe_timeout].
+pub const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Quote currencies recognized by [symbol].
+const QUOTES: [&str; 10] = [
+    "usdt", "usdc", "usd", "eur", "gbp", "cad", "jpy", "chf", "btc", "eth",
+];
+
+/// Returns `pair` as a Kraken symbol, for example `ethbtc` becomes `ETH/BTC`.
+///
+/// Kraken symbols separate the base and the quote currencies, pairs which already contain a `/` are only uppercased,
+/// otherwise the quote is recognized from [QUOTES]. Pairs with an unknown quote are only uppercased,
+/// which Kraken rejects, ending the stream.
+pub fn symbol(pair: &str) -> String {
+    let pair = pair.to_uppercase();
+    if pair.contains('/') {
+        return pair;
+    }
+    QUOTES
+        .iter()
+        .map(|quote| quote.to_uppercase())
+        .find(|quote| pair.len() > quote.len() && pair.ends_with(quote.as_str()))
+        .map(|quote| format!("{}/{}", &pair[..pair.len() - quote.len()], quote))
+        .unwrap_or(pair)
+}
+
+/// Returns the message which subscribes to the book of `symbol` with `depth` levels per side.
+pub fn subscribe_message(symbol: &str, depth: usize) -> String {
+    format!(
+        r#"{{"method":"subscribe","params":{{"channel":"book","symbol":["{}"],"depth":{}}}}}"#,
+        symbol, depth
+    )
+}
+
+/// Establishes a new connection to Kraken and returns a [Stream] of [KrakenInput].
+///
+/// The subscribe message is rate limited by [SourceConfig::throttle].
+/// Waits for `limiter` before connecting. Raw text frames are forwarded to [SourceConfig::raw_messages] before parsing.
+async fn get_stream_inner<B: Backoff>(
+    url: &Url,
+    subscribe_message: String,
+    // Backoff is not Clone.
+    backoff: impl Fn() -> B,
+    config: &SourceConfig,
+    limiter: &ReconnectLimiter,
+) -> impl Stream<Item = Result<KrakenInput, SourceError>> {
+    limiter.wait().await;
+    retry_notify(
+        backoff(),
+        || async {
+            let mut socket = connect(url, config.connection_timeout).await?;
+            config.throttle.acquire().await;
+            socket.send(subscribe_message.clone().into()).await?;
+            Ok(socket)
+        },
+        |err, _| eprintln!("Error creating Kraken connection: {}, retrying", err),
+    )
+    .await
+    .expect("Could not open connection to Kraken")
+    .filter_map({
+        let raw_messages = config.raw_messages.clone();
+        move |item| {
+            forward_raw(raw_messages.as_ref(), &item);
+            parse_message(item)
+        }
+    })
+}
+
+/// Parses a websocket message from Kraken, returns [None] for messages that should be ignored.
+fn parse_message(
+    item: Result<Message, tungstenite::Error>,
+) -> Option<Result<KrakenInput, SourceError>> {
+    match item {
+        Ok(Message::Text(mut text)) => match simd_json::from_str::<KrakenInput>(&mut text) {
+            Ok(KrakenInput::Channel(KrakenChannel::Heartbeat))
+            | Ok(KrakenInput::Channel(KrakenChannel::Status)) => None,
+            Ok(input) => Some(Ok(input)),
+            Err(err) => Some(Err(tungstenite::Error::Protocol(Cow::Owned(
+                err.to_string(),
+            ))
+            .into())),
+        },
+        Ok(Message::Close(frame)) => Some(Err(SourceError::Closed(close_action(frame.as_ref())))),
+        Err(err) => Some(Err(err.into())),
+        Ok(_) => {
+            // Ignore ping, pong and binary messages
+            None
+        }
+    }
+}
+
+/// Returns `levels` as [Levels](Level), a `qty` of 0 is kept as an amount of 0, which removes the level.
+fn into_levels<'a>(levels: impl Iterator<Item = &'a KrakenLevel>) -> Vec<Level> {
+    levels
+        .map(|level| Level {
+            price: level.price,
+            amount: level.qty,
+        })
+        .collect()
+}
+
+/// Applies a `book` message to `book`, returns the [InputDiff] which brings other books to the same state
+/// and the timestamp of the message, or [None] if it's an update without a snapshot.
+///
+/// Kraken doesn't send removals for levels which fall out of the subscribed depth, so `book` is truncated
+/// to [DEFAULT_DEPTH] and the levels dropped are added to the diff as removals.
+fn apply_message(
+    book: &mut DiffBook,
+    kind: KrakenBookKind,
+    data: &[KrakenBookData],
+) -> Option<(Option<String>, InputDiff)> {
+    let asks = into_levels(data.iter().flat_map(|data| data.asks.iter()));
+    let bids = into_levels(data.iter().flat_map(|data| data.bids.iter()));
+    let mut diff = match kind {
+        KrakenBookKind::Snapshot => InputDiff::snapshot(Exchange::Kraken, asks, bids),
+        KrakenBookKind::Update => InputDiff::new(Exchange::Kraken, asks, bids),
+    };
+    if !book.apply(&diff) {
+        return None;
+    }
+    let (asks, bids) = book.truncate(DEFAULT_DEPTH);
+    diff.extend(asks, bids);
+    let timestamp = data.last().and_then(|data| data.timestamp.clone());
+    Some((timestamp, diff))
+}
+
+/// Creates a new [InputUpdate] [Stream] from the provided `pair` by connecting to the Kraken [websocket API](https://docs.kraken.com/api/docs/websocket-v2/book),
+/// see [symbol] for the accepted pairs.
+/// The stream is resilient and will retry if errors happen. If the pair is not valid, the stream ends.
+/// Subscribe messages are rate limited by [SourceConfig::throttle] so reconnection storms don't exceed Kraken's limits.
+pub fn get_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputUpdate> {
+    into_updates(Exchange::Kraken, get_diff_stream(pair, backoff, config))
+}
+
+/// Same as [get_stream] but emits the changes of the book as [InputDiffs](InputDiff) instead of its top,
+/// to be merged with [MergeState::apply_diff](crate::merge::MergeState::apply_diff).
+///
+/// After every reconnection the next diff is a snapshot.
+pub fn get_diff_stream<B: Backoff>(
+    pair: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputDiff> {
+    get_url_stream(
+        Url::parse(URL).expect("Invalid Kraken url"),
+        subscribe_message(&Exchange::Kraken.symbol(&pair), DEFAULT_DEPTH),
+        backoff,
+        config,
+    )
+}
+
+/// Same as [get_diff_stream] but connects to `url` and sends the provided `subscribe_message`.
+fn get_url_stream<B: Backoff>(
+    url: Url,
+    subscribe_message: String,
+    backoff: impl Fn() -> B + Clone,
+    config: SourceConfig,
+) -> impl Stream<Item = InputDiff> {
+    let subscribe_timeout = config.subscribe_timeout.unwrap_or(SUBSCRIBE_TIMEOUT);
+    stream! {
+        let limiter = ReconnectLimiter::new(config.min_reconnect_interval);
+        loop{
+            let mut s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+            let mut ack_deadline = Some(Instant::now() + subscribe_timeout);
+            let mut book = DiffBook::new();
+            let mut stall = StallDetector::new(config.stall_threshold);
+
+            while let Some(value) = next_before(&mut s, ack_deadline).await {
+                match value{
+                    Ok(KrakenInput::Channel(KrakenChannel::Book{kind, data})) => {
+                        config.record_message();
+                        let (timestamp, diff) = match apply_message(&mut book, kind, &data) {
+                            Some(diff) => diff,
+                            None => {
+                                eprintln!("Kraken update received before the snapshot, discarding");
+                                continue;
+                            }
+                        };
+                        let keyed = (timestamp, diff);
+                        if stall.is_stalled(&keyed) {
+                            eprintln!("Kraken stream stalled, restarting");
+                            config.notify_reconnect(Exchange::Kraken, ReconnectReason::Stalled);
+                            s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                            ack_deadline = Some(Instant::now() + subscribe_timeout);
+                            book.clear();
+                            stall.reset();
+                        } else {
+                            yield keyed.1;
+                        }
+                    }
+                    Ok(KrakenInput::Channel(_)) => {}
+                    Ok(KrakenInput::Subscribed(KrakenSubscribed{success: true, ..})) => {
+                        ack_deadline = None;
+                    }
+                    Ok(KrakenInput::Subscribed(KrakenSubscribed{error, ..})) => {
+                        eprintln!("Kraken rejected the subscription: {}, stopping", error.unwrap_or_default());
+                        return;
+                    }
+                    Err(SourceError::AckTimeout) => {
+                        eprintln!("Kraken didn't acknowledge the subscription in time, reconnecting");
+                        config.notify_reconnect(Exchange::Kraken, ReconnectReason::AckTimeout);
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        book.clear();
+                        stall.reset();
+                    }
+                    Err(SourceError::Closed(CloseAction::Terminate)) => {
+                        eprintln!("Kraken closed the connection with an unrecoverable error, stopping");
+                        return;
+                    }
+                    Err(SourceError::Closed(action)) => {
+                        eprintln!("Kraken closed the connection, reconnecting");
+                        config.notify_reconnect(Exchange::Kraken, ReconnectReason::Closed);
+                        if let CloseAction::Delay(delay) = action {
+                            sleep(delay).await;
+                        }
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        book.clear();
+                        stall.reset();
+                    }
+                    Err(err)=>{
+                        eprintln!("Unexpected error in Kraken stream: {}, restarting",err);
+                        config.notify_reconnect(Exchange::Kraken, ReconnectReason::Error);
+                        s = get_stream_inner(&url,subscribe_message.clone(),backoff.clone(),&config,&limiter).await;
+                        ack_deadline = Some(Instant::now() + subscribe_timeout);
+                        book.clear();
+                        stall.reset();
+                    }
+                }
+            }
+            eprintln!("Kraken stream stopped unexpectedly, restarting");
+            config.notify_reconnect(Exchange::Kraken, ReconnectReason::StreamEnded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BackoffConfig, MockWsServer, TokenBucket};
+    use super::*;
+    use crate::arrayvec;
+
+    fn book_message(kind: &str, bids: &str, asks: &str) -> String {
+        format!(
+            r#"{{"channel":"book","type":"{}","data":[{{"symbol":"ETH/BTC","bids":[{}],"asks":[{}],"checksum":1,"timestamp":"2023-10-06T17:35:55.440295Z"}}]}}"#,
+            kind, bids, asks
+        )
+    }
+
+    fn parse(text: String) -> Option<Result<KrakenInput, SourceError>> {
+        parse_message(Ok(Message::Text(text)))
+    }
+
+    #[test]
+    fn test_symbol() {
+        assert_eq!(symbol("ethbtc"), "ETH/BTC");
+        assert_eq!(symbol("BTCUSDT"), "BTC/USDT");
+        assert_eq!(symbol("btcusd"), "BTC/USD");
+        assert_eq!(symbol("eth/btc"), "ETH/BTC");
+        assert_eq!(symbol("usd"), "USD");
+        assert_eq!(symbol("ethxyz"), "ETHXYZ");
+        assert_eq!(
+            subscribe_message("ETH/BTC", 10),
+            r#"{"method":"subscribe","params":{"channel":"book","symbol":["ETH/BTC"],"depth":10}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert!(matches!(
+            parse(book_message("snapshot", r#"{"price":0.5,"qty":1}"#, "")),
+            Some(Ok(KrakenInput::Channel(KrakenChannel::Book {
+                kind: KrakenBookKind::Snapshot,
+                ..
+            })))
+        ));
+        assert!(matches!(
+            parse(book_message("update", "", r#"{"price":1,"qty":0}"#)),
+            Some(Ok(KrakenInput::Channel(KrakenChannel::Book {
+                kind: KrakenBookKind::Update,
+                ..
+            })))
+        ));
+        assert!(matches!(
+            parse(
+                r#"{"method":"subscribe","result":{"channel":"book","depth":10,"snapshot":true,"symbol":"ETH/BTC"},"success":true,"time_in":"2023-10-06T17:35:55.000000Z","time_out":"2023-10-06T17:35:55.000001Z"}"#.to_string()
+            ),
+            Some(Ok(KrakenInput::Subscribed(KrakenSubscribed { success: true, .. })))
+        ));
+        assert!(matches!(
+            parse(
+                r#"{"error":"Currency pair not supported ETH/XYZ","method":"subscribe","success":false,"symbol":"ETH/XYZ"}"#.to_string()
+            ),
+            Some(Ok(KrakenInput::Subscribed(KrakenSubscribed { success: false, error: Some(_) })))
+        ));
+
+        // Heartbeats and status messages are consumed silently.
+        assert!(parse(r#"{"channel":"heartbeat"}"#.to_string()).is_none());
+        assert!(parse(
+            r#"{"channel":"status","type":"update","data":[{"api_version":"v2","system":"online","version":"2.0.0"}]}"#.to_string()
+        )
+        .is_none());
+        assert!(parse_message(Ok(Message::Ping(vec![]))).is_none());
+
+        assert!(matches!(
+            parse(book_message("snapshot", r#"{"price":-0.5,"qty":1}"#, "")),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse(r#"{"channel":"ticker"}"#.to_string()),
+            Some(Err(SourceError::Ws(_)))
+        ));
+        assert!(matches!(
+            parse_message(Ok(Message::Close(None))),
+            Some(Err(SourceError::Closed(CloseAction::Reconnect)))
+        ));
+    }
+
+    #[test]
+    fn test_apply_message() {
+        let data = |kind: &str, bids: &str, asks: &str| match parse(book_message(kind, bids, asks))
+        {
+            Some(Ok(KrakenInput::Channel(KrakenChannel::Book { kind, data }))) => (kind, data),
+            _ => panic!("Invalid book message"),
+        };
+        let mut book = DiffBook::new();
+        // Applying the diffs to another book, like the one of a MergeState, keeps both the same.
+        let mut other = DiffBook::new();
+
+        // Updates before the snapshot are discarded.
+        let (kind, update) = data("update", r#"{"price":0.5,"qty":1}"#, "");
+        assert!(apply_message(&mut book, kind, &update).is_none());
+
+        let (kind, snapshot) = data(
+            "snapshot",
+            r#"{"price":0.5,"qty":1},{"price":0.4,"qty":2}"#,
+            r#"{"price":1,"qty":1},{"price":2,"qty":1}"#,
+        );
+        let (timestamp, diff) = apply_message(&mut book, kind, &snapshot).unwrap();
+        assert_eq!(timestamp.as_deref(), Some("2023-10-06T17:35:55.440295Z"));
+        assert_eq!(
+            diff,
+            InputDiff::snapshot(
+                Exchange::Kraken,
+                vec![lvl!(1., 1.), lvl!(2., 1.)],
+                vec![lvl!(0.5, 1.), lvl!(0.4, 2.)],
+            )
+        );
+        assert!(other.apply(&diff));
+
+        // Removes 0.5, replaces 2 and adds 1.5.
+        let (kind, changes) = data(
+            "update",
+            r#"{"price":0.5,"qty":0}"#,
+            r#"{"price":2,"qty":3},{"price":1.5,"qty":1}"#,
+        );
+        let (_, diff) = apply_message(&mut book, kind, &changes).unwrap();
+        assert!(other.apply(&diff));
+        assert_eq!(
+            book.to_update(Exchange::Kraken),
+            InputUpdate::new(
+                Exchange::Kraken,
+                arrayvec![lvl!(1., 1.), lvl!(1.5, 1.), lvl!(2., 3.)],
+                arrayvec![lvl!(0.4, 2.)],
+            )
+        );
+
+        // Levels past the subscribed depth are dropped and sent as removals.
+        let deep = (0..DEFAULT_DEPTH + 5)
+            .map(|i| format!(r#"{{"price":{},"qty":1}}"#, 10 + i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let (kind, changes) = data("update", "", &deep);
+        let (_, diff) = apply_message(&mut book, kind, &changes).unwrap();
+        assert!(other.apply(&diff));
+        assert_eq!(book.asks().count(), DEFAULT_DEPTH);
+        assert_eq!(
+            other.asks().collect::<Vec<_>>(),
+            book.asks().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            book.asks().last(),
+            Some(lvl!((10 + DEFAULT_DEPTH - 4) as f64, 1.))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kraken_reconnect_on_close() {
+        let subscribed = || {
+            Message::Text(
+                r#"{"method":"subscribe","result":{"channel":"book","depth":10,"snapshot":true,"symbol":"ETH/BTC"},"success":true}"#
+                    .to_string(),
+            )
+        };
+        let server = MockWsServer::start(vec![
+            vec![
+                subscribed(),
+                Message::Text(r#"{"channel":"heartbeat"}"#.to_string()),
+                Message::Text(book_message(
+                    "snapshot",
+                    r#"{"price":0.5,"qty":1}"#,
+                    r#"{"price":1,"qty":1}"#,
+                )),
+                Message::Close(None),
+            ],
+            vec![
+                subscribed(),
+                // Discarded, the book is rebuilt from the next snapshot.
+                Message::Text(book_message("update", "", r#"{"price":3,"qty":1}"#)),
+                Message::Text(book_message(
+                    "snapshot",
+                    r#"{"price":0.5,"qty":1}"#,
+                    r#"{"price":2,"qty":1}"#,
+                )),
+            ],
+        ])
+        .await;
+
+        let config = SourceConfig {
+            throttle: TokenBucket::new(2, Duration::from_secs(1)),
+            min_reconnect_interval: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut stream = Box::pin(into_updates(
+            Exchange::Kraken,
+            get_url_stream(
+                server.url(),
+                subscribe_message("ETH/BTC", DEFAULT_DEPTH),
+                BackoffConfig::default().factory(),
+                config,
+            ),
+        ));
+
+        let mut prices = Vec::new();
+        for _ in 0..2 {
+            let update = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .expect("Timed out waiting for an update")
+                .unwrap();
+            let (_, asks, _) = update.take();
+            prices.push(
+                asks.iter()
+                    .map(|level| Into::<f64>::into(level.price))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(prices, vec![vec![1.], vec![2.]]);
+    }
+}
@@ -0,0 +1,49 @@
+use futures_util::SinkExt;
+use tokio::{net::TcpListener, spawn, task::JoinHandle};
+use tokio_stream::StreamExt;
+use tungstenite::Message;
+use url::Url;
+
+/// Local plain websocket server which plays a script of messages to each connection, for testing the sources.
+pub struct MockWsServer {
+    url: Url,
+    handle: JoinHandle<()>,
+}
+
+impl MockWsServer {
+    /// Starts a server on an ephemeral port which sends the messages of `connections[i]` to the `i`th connection,
+    /// connections after the last one are dropped.
+    ///
+    /// Connections whose script doesn't end with a [Message::Close] are kept open until the client goes away.
+    pub async fn start(connections: Vec<Vec<Message>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+        let handle = spawn(async move {
+            for script in connections {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                spawn(async move {
+                    for message in script {
+                        if socket.send(message).await.is_err() {
+                            return;
+                        }
+                    }
+                    // Drain until the client closes, Close replies are sent by tungstenite.
+                    while let Some(Ok(_)) = socket.next().await {}
+                });
+            }
+        });
+        Self { url, handle }
+    }
+
+    /// Returns the `ws://` url of the server.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+}
+
+impl Drop for MockWsServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
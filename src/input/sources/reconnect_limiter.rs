@@ -0,0 +1,69 @@
+use std::{sync::Mutex, time::Duration};
+use tokio::time::{sleep_until, Instant};
+
+#[derive(Debug)]
+/// Enforces a minimum interval between connections to an exchange.
+///
+/// Backoffs restart after every successful connection, so a connection which fails right after being established
+/// would otherwise be retried in a tight loop.
+pub struct ReconnectLimiter {
+    min_interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl ReconnectLimiter {
+    /// Returns a new [ReconnectLimiter] which spaces connections by at least `min_interval`.
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Waits until `min_interval` has passed since the previous call, the first call returns right away.
+    pub async fn wait(&self) {
+        let last = *self.last.lock().expect("ReconnectLimiter mutex poisoned");
+        if let Some(last) = last {
+            sleep_until(last + self.min_interval).await;
+        }
+        *self.last.lock().expect("ReconnectLimiter mutex poisoned") = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spacing() {
+        tokio::time::pause();
+        let limiter = ReconnectLimiter::new(Duration::from_secs(1));
+        let start = Instant::now();
+
+        limiter.wait().await;
+        assert!(start.elapsed() < Duration::from_millis(1));
+
+        // A connection which closes right away.
+        let mut previous = Instant::now();
+        for _ in 0..5 {
+            limiter.wait().await;
+            assert!(previous.elapsed() >= Duration::from_secs(1));
+            previous = Instant::now();
+        }
+        assert!(start.elapsed() >= Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_millis(5100));
+    }
+
+    #[tokio::test]
+    async fn test_slow_connections_dont_wait() {
+        tokio::time::pause();
+        let limiter = ReconnectLimiter::new(Duration::from_secs(1));
+        limiter.wait().await;
+
+        // The connection lasted longer than the interval.
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let start = Instant::now();
+        limiter.wait().await;
+        assert!(start.elapsed() < Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,83 @@
+#[derive(Debug)]
+/// Detects feeds which are stuck re-sending the same update, they look live but are actually frozen.
+///
+/// Complements idle timeouts, which only catch silence and not repetition.
+pub struct StallDetector<T> {
+    /// Number of consecutive identical updates after which the feed is considered stalled, [None] disables detection.
+    threshold: Option<usize>,
+    last: Option<T>,
+    repeats: usize,
+}
+
+impl<T: PartialEq + Clone> StallDetector<T> {
+    /// Returns a new [StallDetector] which considers the feed stalled after `threshold` consecutive identical updates.
+    pub fn new(threshold: Option<usize>) -> Self {
+        Self {
+            threshold,
+            last: None,
+            repeats: 0,
+        }
+    }
+
+    /// Records `update` and returns true if it's the `threshold`th consecutive identical update.
+    /// `update` is only cloned if detection is enabled and it differs from the previous one.
+    ///
+    /// `update` should include the exchange's timestamp or sequence number, so that a quiet market
+    /// which keeps sending the same levels isn't mistaken for a stalled feed.
+    pub fn is_stalled(&mut self, update: &T) -> bool {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        if self.last.as_ref() == Some(update) {
+            self.repeats += 1;
+        } else {
+            self.last = Some(update.clone());
+            self.repeats = 1;
+        }
+        self.repeats >= threshold
+    }
+
+    /// Forgets the previous updates, should be called on reconnection.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.repeats = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stalled() {
+        let mut detector = StallDetector::new(Some(3));
+        assert!(!detector.is_stalled(&(1, "a")));
+        assert!(!detector.is_stalled(&(1, "a")));
+        assert!(detector.is_stalled(&(1, "a")));
+
+        detector.reset();
+        assert!(!detector.is_stalled(&(1, "a")));
+    }
+
+    #[test]
+    fn test_genuine_updates() {
+        let mut detector = StallDetector::new(Some(2));
+        // Same content with a new timestamp.
+        for timestamp in 0..10 {
+            assert!(!detector.is_stalled(&(timestamp, "a")));
+        }
+        // Interleaved repetitions aren't consecutive.
+        assert!(!detector.is_stalled(&(1, "a")));
+        assert!(!detector.is_stalled(&(1, "b")));
+        assert!(!detector.is_stalled(&(1, "a")));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut detector = StallDetector::new(None);
+        for _ in 0..10 {
+            assert!(!detector.is_stalled(&(1, "a")));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{sleep, Instant};
+
+#[derive(Debug, Clone)]
+/// Token bucket rate limiter for the control messages that sources send to an exchange.
+///
+/// Clones share the same bucket, so a single [TokenBucket] should be created per exchange and
+/// handed to its source, that way every reconnection attempt draws from the same budget.
+pub struct TokenBucket {
+    capacity: f64,
+    /// Time it takes to refill one token.
+    interval: Duration,
+    state: Arc<Mutex<TokenBucketState>>,
+}
+
+#[derive(Debug)]
+struct TokenBucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Returns a new full [TokenBucket] which allows bursts of up to `capacity` messages
+    /// and refills at a rate of `capacity` messages every `period`.
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        assert!(capacity > 0, "TokenBucket capacity must be greater than 0");
+        Self {
+            capacity: capacity as f64,
+            interval: period / capacity,
+            state: Arc::new(Mutex::new(TokenBucketState {
+                tokens: capacity as f64,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().expect("TokenBucket mutex poisoned");
+                let now = Instant::now();
+                let refilled = now.duration_since(state.last_refill).as_secs_f64()
+                    / self.interval.as_secs_f64();
+                state.tokens = (state.tokens + refilled).min(self.capacity);
+                state.last_refill = now;
+
+                if state.tokens >= 1. {
+                    state.tokens -= 1.;
+                    return;
+                }
+                self.interval.mul_f64(1. - state.tokens)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst() {
+        tokio::time::pause();
+        let bucket = TokenBucket::new(2, Duration::from_secs(1));
+        let start = Instant::now();
+
+        bucket.acquire().await;
+        bucket.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(1));
+
+        bucket.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_rate() {
+        tokio::time::pause();
+        let bucket = TokenBucket::new(2, Duration::from_secs(1));
+        // Shared between reconnection attempts.
+        let reconnecting = bucket.clone();
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            reconnecting.acquire().await;
+        }
+        // 2 messages in the initial burst, then one every 500ms.
+        assert!(start.elapsed() >= Duration::from_secs(4));
+        assert!(start.elapsed() < Duration::from_millis(4100));
+    }
+
+    #[tokio::test]
+    async fn test_refill_is_capped() {
+        tokio::time::pause();
+        let bucket = TokenBucket::new(2, Duration::from_secs(1));
+        tokio::time::advance(Duration::from_secs(60)).await;
+        let start = Instant::now();
+
+        bucket.acquire().await;
+        bucket.acquire().await;
+        bucket.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,97 @@
+use super::super::InputUpdate;
+use async_stream::stream;
+use std::time::Duration;
+use tokio::{
+    select,
+    time::{sleep_until, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
+
+/// Returns a [Stream] with the updates of `updates`, a source's output, at most once every `min_interval`.
+///
+/// Updates which arrive before `min_interval` has passed since the previous one are held back, only the latest one
+/// is kept and it's emitted once the interval passes, so the merger never sees an outdated book.
+/// Protects the merger from exchanges which flood updates faster than they are useful.
+pub fn throttle_updates(
+    updates: impl Stream<Item = InputUpdate>,
+    min_interval: Duration,
+) -> impl Stream<Item = InputUpdate> {
+    stream! {
+        tokio::pin!(updates);
+        // Latest update held back by the throttle.
+        let mut pending = None;
+        let mut next_allowed = Instant::now();
+        loop {
+            let update = select! {
+                update = updates.next() => match update {
+                    Some(update) if pending.is_none() && Instant::now() >= next_allowed => Some(update),
+                    Some(update) => {
+                        pending = Some(update);
+                        None
+                    }
+                    None => break,
+                },
+                _ = sleep_until(next_allowed), if pending.is_some() => pending.take(),
+            };
+            if let Some(update) = update {
+                next_allowed = Instant::now() + min_interval;
+                yield update;
+            }
+        }
+        // The source ended, the latest update is not dropped.
+        if let Some(update) = pending {
+            yield update;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{arrayvec, input::Exchange};
+    use tokio::time::sleep;
+
+    fn update(price: usize) -> InputUpdate {
+        InputUpdate::new(
+            Exchange::Binance,
+            arrayvec![lvl!(price as f64, 1.)],
+            arrayvec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_throttle_updates() {
+        tokio::time::pause();
+        // Sends an update every millisecond.
+        let flood = stream! {
+            for price in 1..=100 {
+                sleep(Duration::from_millis(1)).await;
+                yield update(price);
+            }
+        };
+        let throttled = throttle_updates(flood, Duration::from_millis(10))
+            .collect::<Vec<_>>()
+            .await;
+
+        // One every 10ms over 100ms, plus the first and the flushed one.
+        assert!(throttled.len() <= 12, "{} updates", throttled.len());
+        assert!(throttled.len() >= 10, "{} updates", throttled.len());
+        assert_eq!(throttled.first(), Some(&update(1)));
+        assert_eq!(throttled.last(), Some(&update(100)));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_updates_slow_source() {
+        tokio::time::pause();
+        let slow = stream! {
+            for price in 1..=3 {
+                sleep(Duration::from_millis(20)).await;
+                yield update(price);
+            }
+        };
+        let throttled = throttle_updates(slow, Duration::from_millis(10))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(throttled, vec![update(1), update(2), update(3)]);
+    }
+}
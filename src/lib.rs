@@ -1,10 +1,17 @@
 use std::cmp::Ordering;
 
+pub mod audit;
 #[macro_use]
 pub mod input;
 pub mod merge;
+pub mod metrics;
 pub mod proto;
+#[cfg(test)]
+mod regression;
 pub mod serve;
+#[cfg(any(test, feature = "test-util"))]
+pub mod synthetic;
+pub mod transform;
 
 /// Number of items in the channel between the parsers and the merger.
 pub const CHANNEL_SIZE: usize = 100;
@@ -17,6 +24,13 @@ pub fn is_sorted<T>(levels: &[T], cmp_fn: impl Fn(&T, &T) -> Ordering) -> bool {
         .all(|w| !matches!(cmp_fn(&w[0], &w[1]), Ordering::Greater))
 }
 
+/// Same as [is_sorted] but returns false if any two adjacent elements are equal.
+pub fn is_sorted_strict<T>(levels: &[T], cmp_fn: impl Fn(&T, &T) -> Ordering) -> bool {
+    levels
+        .windows(2)
+        .all(|w| matches!(cmp_fn(&w[0], &w[1]), Ordering::Less))
+}
+
 #[cfg(test)]
 mod test {
     use input::Level;
@@ -45,4 +59,41 @@ mod test {
         assert!(!is_sorted(&[lvl!(1., 1.), lvl!(1., 2.)], Level::cmp_ask));
         assert!(!is_sorted(&[lvl!(1., 1.), lvl!(1., 2.)], Level::cmp_bid));
     }
+
+    #[test]
+    fn test_is_sorted_strict() {
+        assert!(is_sorted_strict(&[], Level::cmp_ask));
+        assert!(is_sorted_strict(&[lvl!(1., 1.)], Level::cmp_ask));
+
+        assert!(!is_sorted_strict(
+            &[lvl!(1., 1.), lvl!(1., 1.)],
+            Level::cmp_ask
+        ));
+        assert!(!is_sorted_strict(
+            &[lvl!(1., 1.), lvl!(1., 1.)],
+            Level::cmp_bid
+        ));
+
+        assert!(is_sorted_strict(
+            &[lvl!(1., 1.), lvl!(2., 1.)],
+            Level::cmp_ask
+        ));
+        assert!(is_sorted_strict(
+            &[lvl!(2., 1.), lvl!(1., 1.)],
+            Level::cmp_bid
+        ));
+
+        assert!(!is_sorted_strict(
+            &[lvl!(2., 1.), lvl!(1., 1.)],
+            Level::cmp_ask
+        ));
+        assert!(!is_sorted_strict(
+            &[lvl!(1., 1.), lvl!(2., 1.)],
+            Level::cmp_bid
+        ));
+
+        let cmp_price = |a: &Level, b: &Level| a.price.cmp(&b.price);
+        assert!(is_sorted_strict(&[lvl!(1., 1.), lvl!(2., 1.)], cmp_price));
+        assert!(!is_sorted_strict(&[lvl!(1., 2.), lvl!(1., 1.)], cmp_price));
+    }
 }
@@ -0,0 +1,203 @@
+use crate::input::Exchange;
+use std::collections::VecDeque;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex,
+};
+use std::time::{Duration, Instant};
+
+/// [FeedQualityMonitor::late_rate] above which a warning is logged.
+pub const LATE_RATE_THRESHOLD: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+/// Counts events and computes their rate over a sliding `window`.
+///
+/// Can be used with [merge_with_callback](crate::merge::merge_with_callback) to track how many
+/// summaries per second the merger is producing, in order to detect stalls or floods.
+pub struct RateCounter {
+    window: Duration,
+    /// Instants of the events inside the window, oldest first.
+    events: VecDeque<Instant>,
+    total: u64,
+}
+
+impl RateCounter {
+    /// Returns a new [RateCounter] which computes rates over `window`.
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: Duration) -> Self {
+        assert!(
+            window > Duration::from_secs(0),
+            "RateCounter window must not be zero"
+        );
+        Self {
+            window,
+            events: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    /// Records an event now.
+    pub fn record(&mut self) {
+        self.record_at(Instant::now())
+    }
+
+    /// Records an event at `now`, which must not be earlier than previously recorded events.
+    pub fn record_at(&mut self, now: Instant) {
+        self.total += 1;
+        self.events.push_back(now);
+        self.evict(now);
+    }
+
+    /// Returns the number of events recorded since the [RateCounter] was created.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the rate of events per second over the window ending now.
+    pub fn rate(&mut self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    /// Returns the rate of events per second over the window ending at `now`.
+    pub fn rate_at(&mut self, now: Instant) -> f64 {
+        self.evict(now);
+        self.events.len() as f64 / self.window.as_secs_f64()
+    }
+
+    /// Removes the events which are outside of the window ending at `now`.
+    fn evict(&mut self, now: Instant) {
+        while let Some(oldest) = self.events.front() {
+            if now.saturating_duration_since(*oldest) < self.window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Tracks how often messages from an exchange arrive later than `expected_interval` after the previous one.
+///
+/// Can be shared between the source which records messages and a task which reports [FeedQualityMonitor::late_rate].
+pub struct FeedQualityMonitor {
+    exchange: Exchange,
+    expected_interval: Duration,
+    last_message: Mutex<Option<Instant>>,
+    late_count: AtomicU64,
+    total_count: AtomicU64,
+    /// Whether the late rate is currently above [LATE_RATE_THRESHOLD], to warn only when crossing it.
+    warned: AtomicBool,
+}
+
+impl FeedQualityMonitor {
+    /// Returns a new [FeedQualityMonitor] for `exchange` which expects a message every `expected_interval`.
+    pub fn new(exchange: Exchange, expected_interval: Duration) -> Self {
+        Self {
+            exchange,
+            expected_interval,
+            last_message: Mutex::new(None),
+            late_count: AtomicU64::new(0),
+            total_count: AtomicU64::new(0),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Records a message received now.
+    pub fn record_message(&self) {
+        self.record_message_at(Instant::now())
+    }
+
+    /// Records a message received at `now`.
+    ///
+    /// Logs a warning when the late rate goes above [LATE_RATE_THRESHOLD].
+    pub fn record_message_at(&self, now: Instant) {
+        let previous = self
+            .last_message
+            .lock()
+            .expect("FeedQualityMonitor mutex poisoned")
+            .replace(now);
+
+        self.total_count.fetch_add(1, Ordering::Relaxed);
+        if let Some(previous) = previous {
+            if now.saturating_duration_since(previous) > self.expected_interval {
+                self.late_count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let late = self.late_rate() > LATE_RATE_THRESHOLD;
+        if late && !self.warned.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "{} feed quality degraded, {:.1}% of messages are late",
+                self.exchange,
+                self.late_rate() * 100.
+            );
+        } else if !late {
+            self.warned.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the fraction of messages which arrived late, 0 if no messages have been received.
+    pub fn late_rate(&self) -> f64 {
+        let total = self.total_count.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.;
+        }
+        self.late_count.load(Ordering::Relaxed) as f64 / total as f64
+    }
+
+    /// Returns the [Exchange] being monitored.
+    pub fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_feed_quality_monitor() {
+        let start = Instant::now();
+        let monitor = FeedQualityMonitor::new(Exchange::Binance, Duration::from_millis(100));
+        assert_eq!(monitor.late_rate(), 0.);
+
+        // The first message can't be late.
+        monitor.record_message_at(start);
+        for i in 1..8 {
+            monitor.record_message_at(start + Duration::from_millis(100 * i));
+        }
+        assert_eq!(monitor.late_rate(), 0.);
+
+        // 2 late messages out of 10.
+        monitor.record_message_at(start + Duration::from_millis(1000));
+        monitor.record_message_at(start + Duration::from_millis(1500));
+        assert_eq!(monitor.late_rate(), 0.2);
+        assert!(monitor.warned.load(Ordering::Relaxed));
+
+        for i in 1..=10 {
+            monitor.record_message_at(start + Duration::from_millis(1500 + 50 * i));
+        }
+        assert_eq!(monitor.late_rate(), 0.1);
+        assert!(!monitor.warned.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_rate_counter() {
+        let start = Instant::now();
+        let mut counter = RateCounter::new(Duration::from_secs(2));
+        assert_eq!(counter.rate_at(start), 0.);
+
+        for i in 0..10 {
+            counter.record_at(start + Duration::from_millis(100 * i));
+        }
+        assert_eq!(counter.total(), 10);
+        assert_eq!(counter.rate_at(start + Duration::from_secs(1)), 5.);
+
+        // The first 5 events are outside of the window.
+        assert_eq!(counter.rate_at(start + Duration::from_millis(2450)), 2.5);
+        // Stalled.
+        assert_eq!(counter.rate_at(start + Duration::from_secs(10)), 0.);
+        assert_eq!(counter.total(), 10);
+    }
+}
@@ -0,0 +1,136 @@
+use super::orderbook;
+use std::fmt::Write;
+
+/// Returns a description of the differences between `summary` and the expected levels and spread,
+/// or [None] if they match, see [assert_summary_eq](crate::assert_summary_eq).
+///
+/// Each side lists every level, differing ones are marked with `>`, spreads are compared exactly.
+pub fn summary_mismatch(
+    summary: &orderbook::Summary,
+    asks: &[orderbook::Level],
+    bids: &[orderbook::Level],
+    spread: Option<f64>,
+) -> Option<String> {
+    let mut message = String::new();
+    for (side, expected, actual) in
+        vec![("asks", asks, &summary.asks), ("bids", bids, &summary.bids)]
+    {
+        if expected == actual.as_slice() {
+            continue;
+        }
+        writeln!(
+            message,
+            "{} differ, expected {} levels, got {}:",
+            side,
+            expected.len(),
+            actual.len()
+        )
+        .unwrap();
+        for i in 0..expected.len().max(actual.len()) {
+            let (expected, actual) = (expected.get(i), actual.get(i));
+            let marker = if expected == actual { ' ' } else { '>' };
+            writeln!(
+                message,
+                "{} {}: expected {}, got {}",
+                marker,
+                i,
+                describe(expected),
+                describe(actual)
+            )
+            .unwrap();
+        }
+    }
+    if let Some(spread) = spread {
+        if spread != summary.spread {
+            writeln!(
+                message,
+                "spread differs, expected {}, got {}",
+                spread, summary.spread
+            )
+            .unwrap();
+        }
+    }
+    if message.is_empty() {
+        None
+    } else {
+        Some(message)
+    }
+}
+
+/// Returns `level` as `exchange price x amount`.
+fn describe(level: Option<&orderbook::Level>) -> String {
+    match level {
+        Some(level) => format!("{} {} x {}", level.exchange, level.price, level.amount),
+        None => "nothing".to_string(),
+    }
+}
+
+/// Asserts that the asks, bids and optionally the spread of an [orderbook::Summary] are equal to the expected ones,
+/// on failure the message lists every level and marks the ones which differ.
+///
+/// ```ignore
+/// assert_summary_eq!(summary, asks: [lvl0!(2., 1.)], bids: [lvl1!(1., 1.)], spread: 1.);
+/// ```
+#[macro_export]
+macro_rules! assert_summary_eq {
+    ($summary:expr, asks: [$($ask:expr),* $(,)?], bids: [$($bid:expr),* $(,)?] $(, spread: $spread:expr)? $(,)?) => {{
+        let expected_spread: Option<f64> = None $(.or(Some($spread)))?;
+        if let Some(message) = $crate::proto::summary_mismatch(
+            &$summary,
+            &[$($ask),*],
+            &[$($bid),*],
+            expected_spread,
+        ) {
+            panic!("summaries are not equal\n{}", message);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assert_summary_eq, input::Exchange};
+
+    fn summary() -> orderbook::Summary {
+        orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+            bids: vec![lvl1!(1., 1.)],
+            spread: 1.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_summary_mismatch() {
+        let summary = summary();
+        assert_eq!(
+            summary_mismatch(&summary, &summary.asks, &summary.bids, Some(1.)),
+            None
+        );
+        assert_eq!(
+            summary_mismatch(&summary, &[lvl0!(2., 1.)], &summary.bids, Some(2.)).unwrap(),
+            "asks differ, expected 1 levels, got 2:\n  0: expected binance 2 x 1, got binance 2 x 1\n\
+             > 1: expected nothing, got bitstamp 3 x 1\nspread differs, expected 2, got 1\n"
+        );
+        assert_eq!(
+            summary_mismatch(&summary, &summary.asks, &[lvl0!(1., 1.)], None).unwrap(),
+            "bids differ, expected 1 levels, got 1:\n> 0: expected binance 1 x 1, got bitstamp 1 x 1\n"
+        );
+    }
+
+    #[test]
+    fn test_assert_summary_eq() {
+        assert_summary_eq!(summary(), asks: [lvl0!(2., 1.), lvl1!(3., 1.)], bids: [lvl1!(1., 1.)], spread: 1.);
+        assert_summary_eq!(
+            orderbook::Summary::default(),
+            asks: [],
+            bids: [],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "> 0: expected binance 1 x 1, got bitstamp 1 x 1")]
+    fn test_assert_summary_eq_panics() {
+        assert_summary_eq!(summary(), asks: [lvl0!(2., 1.), lvl1!(3., 1.)], bids: [lvl0!(1., 1.)]);
+    }
+}
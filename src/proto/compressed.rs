@@ -0,0 +1,129 @@
+use super::orderbook;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Level of a [CompressedSummary].
+pub enum CompressedLevel {
+    /// Same level as the one at this index of the same side of the base summary.
+    Unchanged(usize),
+    /// Level which is not in the base summary.
+    Changed(orderbook::Level),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// [orderbook::Summary] where the levels which are also in a base summary are replaced by their index in the base,
+/// see [MergeState::summary_compressed](crate::merge::MergeState::summary_compressed).
+///
+/// Unlike [orderbook::SummaryDiff] the order of the levels is preserved.
+pub struct CompressedSummary {
+    /// Every field of the summary except `asks` and `bids`, which are empty.
+    pub summary: orderbook::Summary,
+    pub asks: Vec<CompressedLevel>,
+    pub bids: Vec<CompressedLevel>,
+}
+
+impl CompressedSummary {
+    /// Returns a new [CompressedSummary] of `current` which references the levels of `base`.
+    pub fn between(base: &orderbook::Summary, current: &orderbook::Summary) -> Self {
+        Self {
+            summary: orderbook::Summary {
+                asks: Vec::new(),
+                bids: Vec::new(),
+                ..current.clone()
+            },
+            asks: compress_levels(&base.asks, &current.asks),
+            bids: compress_levels(&base.bids, &current.bids),
+        }
+    }
+
+    /// Returns the full [orderbook::Summary], `base` must be the summary this was compressed against.
+    ///
+    /// References past the end of `base` are skipped.
+    pub fn decompress(&self, base: &orderbook::Summary) -> orderbook::Summary {
+        orderbook::Summary {
+            asks: decompress_levels(&base.asks, &self.asks),
+            bids: decompress_levels(&base.bids, &self.bids),
+            ..self.summary.clone()
+        }
+    }
+}
+
+/// Returns `current` with every level which is also in `base` replaced by its index in `base`.
+fn compress_levels(
+    base: &[orderbook::Level],
+    current: &[orderbook::Level],
+) -> Vec<CompressedLevel> {
+    current
+        .iter()
+        .map(|level| match base.iter().position(|other| other == level) {
+            Some(i) => CompressedLevel::Unchanged(i),
+            None => CompressedLevel::Changed(level.clone()),
+        })
+        .collect()
+}
+
+/// Returns `levels` with every [CompressedLevel::Unchanged] replaced by the level it references in `base`.
+fn decompress_levels(
+    base: &[orderbook::Level],
+    levels: &[CompressedLevel],
+) -> Vec<orderbook::Level> {
+    levels
+        .iter()
+        .filter_map(|level| match level {
+            CompressedLevel::Unchanged(i) => base.get(*i).cloned(),
+            CompressedLevel::Changed(level) => Some(level.clone()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    #[test]
+    fn test_compress() {
+        let base = orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+            bids: vec![lvl1!(1., 1.), lvl0!(0.5, 1.)],
+            spread: 1.,
+            ..Default::default()
+        };
+        let current = orderbook::Summary {
+            asks: vec![lvl1!(1.5, 2.), lvl0!(2., 1.), lvl1!(3., 2.)],
+            bids: vec![lvl0!(0.5, 1.)],
+            spread: 1.,
+            ..Default::default()
+        };
+
+        let compressed = CompressedSummary::between(&base, &current);
+        assert_eq!(
+            compressed.asks,
+            vec![
+                CompressedLevel::Changed(lvl1!(1.5, 2.)),
+                CompressedLevel::Unchanged(0),
+                CompressedLevel::Changed(lvl1!(3., 2.)),
+            ]
+        );
+        assert_eq!(compressed.bids, vec![CompressedLevel::Unchanged(1)]);
+        assert!(compressed.summary.asks.is_empty());
+        assert_eq!(compressed.summary.spread, 1.);
+        assert_eq!(compressed.decompress(&base), current);
+
+        // Everything references the base.
+        let compressed = CompressedSummary::between(&base, &base);
+        assert!(compressed
+            .asks
+            .iter()
+            .chain(compressed.bids.iter())
+            .all(|level| matches!(level, CompressedLevel::Unchanged(_))));
+        assert_eq!(compressed.decompress(&base), base);
+
+        assert_eq!(
+            compressed.decompress(&orderbook::Summary::default()),
+            orderbook::Summary {
+                spread: 1.,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use super::orderbook;
+
+impl orderbook::Summary {
+    /// Returns `(price, cumulative_amount)` for every ask in ascending price order,
+    /// where `cumulative_amount` is the volume available up to and including `price`.
+    pub fn ask_depth_curve(&self) -> Vec<(f64, f64)> {
+        depth_curve(&self.asks)
+    }
+
+    /// Returns `(price, cumulative_amount)` for every bid in descending price order,
+    /// where `cumulative_amount` is the volume available down to and including `price`.
+    pub fn bid_depth_curve(&self) -> Vec<(f64, f64)> {
+        depth_curve(&self.bids)
+    }
+}
+
+/// Returns the cumulative amount of `levels` at each level, in the same order.
+fn depth_curve(levels: &[orderbook::Level]) -> Vec<(f64, f64)> {
+    levels
+        .iter()
+        .scan(0., |volume, level| {
+            *volume += level.amount;
+            Some((level.price, *volume))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    #[test]
+    fn test_depth_curve() {
+        let summary = orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(2., 0.5), lvl1!(3., 2.)],
+            bids: vec![lvl1!(1., 1.), lvl0!(0.5, 4.)],
+            spread: 1.,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.ask_depth_curve(),
+            vec![(2., 1.), (2., 1.5), (3., 3.5)]
+        );
+        assert_eq!(summary.bid_depth_curve(), vec![(1., 1.), (0.5, 5.)]);
+        assert!(orderbook::Summary::default().ask_depth_curve().is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use super::orderbook;
+use std::cmp::Ordering;
+
+impl orderbook::SummaryDiff {
+    /// Returns a new snapshot [orderbook::SummaryDiff] with every level of `summary`.
+    pub fn snapshot(summary: &orderbook::Summary) -> Self {
+        Self {
+            snapshot: true,
+            spread: summary.spread,
+            microprice: summary.microprice,
+            bids: summary.bids.clone(),
+            asks: summary.asks.clone(),
+            per_exchange_spread: summary.per_exchange_spread.clone(),
+            reference_price: summary.reference_price,
+            contributing_exchanges: summary.contributing_exchanges.clone(),
+            spread_history: summary.spread_history.clone(),
+            evicted_spreads: 0,
+        }
+    }
+
+    /// Returns a new [orderbook::SummaryDiff] with the levels that changed from `previous` to `current`.
+    ///
+    /// Levels are identified by their exchange and price, removed levels are included with an amount of 0.
+    /// The spreads, the reference price and the contributing exchanges are always included,
+    /// the spread history only includes the spreads appended since `previous`.
+    pub fn between(previous: &orderbook::Summary, current: &orderbook::Summary) -> Self {
+        let (evicted_spreads, spread_history) =
+            diff_spread_history(&previous.spread_history, &current.spread_history);
+        Self {
+            snapshot: false,
+            spread: current.spread,
+            microprice: current.microprice,
+            bids: diff_levels(&previous.bids, &current.bids),
+            asks: diff_levels(&previous.asks, &current.asks),
+            per_exchange_spread: current.per_exchange_spread.clone(),
+            reference_price: current.reference_price,
+            contributing_exchanges: current.contributing_exchanges.clone(),
+            spread_history,
+            evicted_spreads: evicted_spreads as u32,
+        }
+    }
+
+    /// Applies the changes in `self` to `summary`, if `self` is a snapshot `summary` is replaced.
+    ///
+    /// Levels with the same price are ordered by descending amount, levels with the same price
+    /// and amount from different exchanges may end up in a different order than in the server.
+    pub fn apply(&self, summary: &mut orderbook::Summary) {
+        summary.spread = self.spread;
+        summary.microprice = self.microprice;
+        summary.per_exchange_spread = self.per_exchange_spread.clone();
+        summary.reference_price = self.reference_price;
+        summary.contributing_exchanges = self.contributing_exchanges.clone();
+        if self.snapshot {
+            summary.spread_history = self.spread_history.clone();
+            summary.bids = self.bids.clone();
+            summary.asks = self.asks.clone();
+            return;
+        }
+
+        let evicted = (self.evicted_spreads as usize).min(summary.spread_history.len());
+        summary.spread_history.drain(..evicted);
+        summary
+            .spread_history
+            .extend_from_slice(&self.spread_history);
+
+        apply_levels(&mut summary.bids, &self.bids);
+        apply_levels(&mut summary.asks, &self.asks);
+        summary
+            .bids
+            .sort_by(|a, b| cmp_price_amount(b.price, a.price, a.amount, b.amount));
+        summary
+            .asks
+            .sort_by(|a, b| cmp_price_amount(a.price, b.price, a.amount, b.amount));
+    }
+}
+
+impl orderbook::OrderbookDiff {
+    /// Returns a new [orderbook::OrderbookDiff] with the levels that were added, removed or changed from `previous` to `current`.
+    pub fn between(previous: &orderbook::Summary, current: &orderbook::Summary) -> Self {
+        Self {
+            asks: Some(orderbook::LevelChanges::between(
+                &previous.asks,
+                &current.asks,
+            )),
+            bids: Some(orderbook::LevelChanges::between(
+                &previous.bids,
+                &current.bids,
+            )),
+        }
+    }
+
+    /// Returns true if no level changed.
+    pub fn is_empty(&self) -> bool {
+        self.asks.iter().chain(self.bids.iter()).all(|changes| {
+            changes.added.is_empty() && changes.removed.is_empty() && changes.changed.is_empty()
+        })
+    }
+}
+
+impl orderbook::LevelChanges {
+    /// Returns a new [orderbook::LevelChanges] from the `previous` to the `current` levels of one side of the book.
+    pub fn between(previous: &[orderbook::Level], current: &[orderbook::Level]) -> Self {
+        let missing_from = |levels: &[orderbook::Level], other: &[orderbook::Level]| {
+            levels
+                .iter()
+                .filter(|level| !other.iter().any(|other| is_same_level(level, other)))
+                .cloned()
+                .collect()
+        };
+        Self {
+            added: missing_from(current, previous),
+            removed: missing_from(previous, current),
+            changed: current
+                .iter()
+                .filter(|level| {
+                    previous
+                        .iter()
+                        .any(|other| is_same_level(level, other) && level.amount != other.amount)
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Returns true if `a` and `b` are the same level, possibly with a different amount.
+fn is_same_level(a: &orderbook::Level, b: &orderbook::Level) -> bool {
+    a.exchange == b.exchange && a.price == b.price
+}
+
+/// Returns the new and changed levels of `current` and the levels of `previous` which are not in `current` with an amount of 0.
+fn diff_levels(
+    previous: &[orderbook::Level],
+    current: &[orderbook::Level],
+) -> Vec<orderbook::Level> {
+    let changed = current.iter().filter(|level| {
+        !previous
+            .iter()
+            .any(|other| is_same_level(level, other) && level.amount == other.amount)
+    });
+    let removed = previous
+        .iter()
+        .filter(|level| !current.iter().any(|other| is_same_level(level, other)))
+        .map(|level| orderbook::Level {
+            amount: 0.,
+            ..level.clone()
+        });
+    changed.cloned().chain(removed).collect()
+}
+
+/// Inserts, updates or removes the levels of `diff` in `levels`.
+fn apply_levels(levels: &mut Vec<orderbook::Level>, diff: &[orderbook::Level]) {
+    for level in diff {
+        match levels.iter().position(|other| is_same_level(level, other)) {
+            Some(i) if level.amount == 0. => {
+                levels.remove(i);
+            }
+            Some(i) => levels[i].amount = level.amount,
+            None if level.amount == 0. => {}
+            None => levels.push(level.clone()),
+        }
+    }
+}
+
+/// Returns the number of spreads evicted from the front of `previous` and the spreads appended to it to get `current`.
+///
+/// Both histories are windows of the same sequence of spreads, so the fewest evicted spreads which leave
+/// a prefix of `current` are picked, evicting every spread always works.
+fn diff_spread_history(previous: &[f64], current: &[f64]) -> (usize, Vec<f64>) {
+    let evicted = (0..previous.len())
+        .find(|&evicted| current.starts_with(&previous[evicted..]))
+        .unwrap_or(previous.len());
+    (evicted, current[previous.len() - evicted..].to_vec())
+}
+
+/// Orders by `price`, ties are ordered by descending `amount`.
+fn cmp_price_amount(price: f64, other_price: f64, amount: f64, other_amount: f64) -> Ordering {
+    price
+        .partial_cmp(&other_price)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| other_amount.partial_cmp(&amount).unwrap_or(Ordering::Equal))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    fn summary() -> orderbook::Summary {
+        orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+            bids: vec![lvl1!(1., 1.), lvl0!(0.5, 1.)],
+            spread: 1.,
+            microprice: 1.5,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut received = orderbook::Summary {
+            asks: vec![lvl0!(5., 1.)],
+            ..Default::default()
+        };
+        let diff = orderbook::SummaryDiff::snapshot(&summary());
+        assert!(diff.snapshot);
+        diff.apply(&mut received);
+        assert_eq!(received, summary());
+    }
+
+    #[test]
+    fn test_between() {
+        assert_eq!(
+            orderbook::SummaryDiff::between(&summary(), &summary()),
+            orderbook::SummaryDiff {
+                spread: 1.,
+                microprice: 1.5,
+                ..Default::default()
+            }
+        );
+
+        let current = orderbook::Summary {
+            asks: vec![lvl1!(1.5, 2.), lvl0!(2., 3.), lvl1!(3., 1.)],
+            bids: vec![lvl0!(0.5, 1.)],
+            spread: 1.,
+            microprice: 0.9,
+            ..Default::default()
+        };
+        let diff = orderbook::SummaryDiff::between(&summary(), &current);
+        assert!(!diff.snapshot);
+        assert_eq!(diff.asks, vec![lvl1!(1.5, 2.), lvl0!(2., 3.)]);
+        assert_eq!(diff.bids, vec![lvl1!(1., 0.)]);
+        assert_eq!(diff.microprice, 0.9);
+
+        let mut received = summary();
+        diff.apply(&mut received);
+        assert_eq!(received, current);
+    }
+
+    #[test]
+    fn test_between_spread_history() {
+        let with_history = |spread_history: Vec<f64>| orderbook::Summary {
+            spread_history,
+            ..summary()
+        };
+        let diff = orderbook::SummaryDiff::between(
+            &with_history(vec![1., 2., 1.]),
+            &with_history(vec![2., 1., 3.]),
+        );
+        assert_eq!(diff.evicted_spreads, 1);
+        assert_eq!(diff.spread_history, vec![3.]);
+
+        let diff = orderbook::SummaryDiff::between(
+            &with_history(vec![1., 2.]),
+            &with_history(vec![1., 2.]),
+        );
+        assert_eq!(diff.evicted_spreads, 0);
+        assert!(diff.spread_history.is_empty());
+
+        // The window emptied before the new spread.
+        let diff =
+            orderbook::SummaryDiff::between(&with_history(vec![1., 2.]), &with_history(vec![3.]));
+        assert_eq!(diff.evicted_spreads, 2);
+        assert_eq!(diff.spread_history, vec![3.]);
+
+        let mut received = with_history(vec![1., 1., 2.]);
+        for current in vec![vec![1., 1., 2., 1.], vec![2., 1., 1.], vec![], vec![4.]] {
+            orderbook::SummaryDiff::between(&received, &with_history(current.clone()))
+                .apply(&mut received);
+            assert_eq!(received.spread_history, current);
+        }
+    }
+
+    #[test]
+    fn test_orderbook_diff() {
+        let diff = orderbook::OrderbookDiff::between(&summary(), &summary());
+        assert!(diff.is_empty());
+
+        let current = orderbook::Summary {
+            asks: vec![lvl1!(1.5, 2.), lvl0!(2., 3.), lvl1!(3., 1.)],
+            bids: vec![lvl0!(0.5, 1.)],
+            ..Default::default()
+        };
+        let diff = orderbook::OrderbookDiff::between(&summary(), &current);
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.asks.unwrap(),
+            orderbook::LevelChanges {
+                added: vec![lvl1!(1.5, 2.)],
+                removed: vec![],
+                changed: vec![lvl0!(2., 3.)],
+            }
+        );
+        assert_eq!(
+            diff.bids.unwrap(),
+            orderbook::LevelChanges {
+                added: vec![],
+                removed: vec![lvl1!(1., 1.)],
+                changed: vec![],
+            }
+        );
+    }
+}
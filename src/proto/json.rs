@@ -0,0 +1,33 @@
+use super::orderbook;
+
+impl orderbook::Summary {
+    /// Returns `self` as a single line JSON object followed by a newline, for [JSON Lines](https://jsonlines.org/) output.
+    pub fn to_json_line(&self) -> String {
+        let mut line = simd_json::to_string(self).expect("Summaries are always serializable");
+        line.push('\n');
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    #[test]
+    fn test_to_json_line() {
+        let summary = orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+            bids: vec![lvl1!(1., 1.)],
+            spread: 1.,
+            ..Default::default()
+        };
+        let mut line = summary.to_json_line();
+        assert!(line.ends_with('\n'));
+        assert_eq!(line.matches('\n').count(), 1);
+
+        let value: simd_json::OwnedValue = simd_json::from_str(&mut line).unwrap();
+        assert_eq!(value["spread"], 1.);
+        assert_eq!(value["asks"][1]["exchange"], "bitstamp");
+    }
+}
@@ -1,3 +1,17 @@
 pub mod orderbook {
     tonic::include_proto!("orderbook");
 }
+#[cfg(test)]
+mod assert_summary;
+#[cfg(test)]
+pub use assert_summary::*;
+mod compressed;
+pub use compressed::*;
+mod depth_curve;
+mod diff;
+mod json;
+mod pretty;
+mod resiliency;
+pub use resiliency::*;
+mod validation;
+pub use validation::*;
@@ -0,0 +1,73 @@
+use super::orderbook;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+impl orderbook::Summary {
+    /// Renders the book for a terminal, one level per line padded to `width` characters
+    /// with the price right-aligned in the left half and the amount left-aligned in the right half.
+    ///
+    /// Asks are on top in red with the best ask at the bottom, followed by the spread centered
+    /// and the bids in green with the best bid at the top.
+    /// Values which don't fit in their half make the line longer than `width`.
+    pub fn prettify(&self, width: usize) -> String {
+        let mut pretty = String::new();
+        for level in self.asks.iter().rev() {
+            push_row(&mut pretty, level, width, RED);
+        }
+        pretty.push_str(&format!(
+            "{:^width$}\n",
+            format!("spread {}", self.spread),
+            width = width
+        ));
+        for level in &self.bids {
+            push_row(&mut pretty, level, width, GREEN);
+        }
+        pretty
+    }
+}
+
+/// Pushes `level` to `pretty` as a line in `color`.
+fn push_row(pretty: &mut String, level: &orderbook::Level, width: usize, color: &str) {
+    let left = width / 2;
+    let right = width.saturating_sub(left + 1);
+    pretty.push_str(&format!(
+        "{}{:>left$} {:<right$}{}\n",
+        color,
+        level.price,
+        level.amount,
+        RESET,
+        left = left,
+        right = right
+    ));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    #[test]
+    fn test_prettify() {
+        let summary = orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(2.5, 0.5)],
+            bids: vec![lvl1!(1., 3.)],
+            spread: 1.,
+            ..Default::default()
+        };
+        let pretty = summary.prettify(12);
+        let lines: Vec<_> = pretty.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "\x1b[31m   2.5 0.5  \x1b[0m",
+                "\x1b[31m     2 1    \x1b[0m",
+                "  spread 1  ",
+                "\x1b[32m     1 3    \x1b[0m",
+            ]
+        );
+
+        assert_eq!(orderbook::Summary::default().prettify(0), "spread 0\n");
+    }
+}
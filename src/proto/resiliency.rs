@@ -0,0 +1,116 @@
+use super::orderbook;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Volume needed to move the price of one side of the book.
+pub struct PriceImpact {
+    /// Total amount of the levels which have to be consumed.
+    pub volume: f64,
+    /// False if the side ran out of levels before the price moved enough,
+    /// in which case `volume` is the amount of the whole side.
+    pub sufficient_depth: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Volume needed to move the price away from the mid on each side of the book, see [orderbook::Summary::resiliency].
+pub struct Resiliency {
+    /// Volume to buy to move the price up.
+    pub asks: PriceImpact,
+    /// Volume to sell to move the price down.
+    pub bids: PriceImpact,
+}
+
+impl orderbook::Summary {
+    /// Returns the volume it takes to move the price `percent`% away from the mid price on each side:
+    /// the amount of the levels better than `mid * (1 ± percent / 100)`.
+    ///
+    /// Returns [None] if either side is empty, since there is no mid price.
+    pub fn resiliency(&self, percent: f64) -> Option<Resiliency> {
+        let mid = (self.asks.first()?.price + self.bids.first()?.price) / 2.;
+        let factor = percent / 100.;
+
+        let ask_target = mid * (1. + factor);
+        let bid_target = mid * (1. - factor);
+        Some(Resiliency {
+            asks: price_impact(&self.asks, |price| price >= ask_target),
+            bids: price_impact(&self.bids, |price| price <= bid_target),
+        })
+    }
+}
+
+/// Accumulates the amount of `levels` until `reached` returns true for the price of one of them.
+fn price_impact(levels: &[orderbook::Level], reached: impl Fn(f64) -> bool) -> PriceImpact {
+    let mut volume = 0.;
+    for level in levels {
+        if reached(level.price) {
+            return PriceImpact {
+                volume,
+                sufficient_depth: true,
+            };
+        }
+        volume += level.amount;
+    }
+    PriceImpact {
+        volume,
+        sufficient_depth: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    fn summary() -> orderbook::Summary {
+        // Mid price is 100.
+        orderbook::Summary {
+            asks: vec![lvl0!(100.5, 1.), lvl1!(100.8, 2.), lvl0!(101.2, 3.)],
+            bids: vec![lvl1!(99.5, 1.), lvl0!(99.2, 2.), lvl1!(98.8, 4.)],
+            spread: 1.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resiliency() {
+        // Moving 1% needs to consume every level up to 101 and down to 99.
+        assert_eq!(
+            summary().resiliency(1.),
+            Some(Resiliency {
+                asks: PriceImpact {
+                    volume: 3.,
+                    sufficient_depth: true
+                },
+                bids: PriceImpact {
+                    volume: 3.,
+                    sufficient_depth: true
+                },
+            })
+        );
+
+        // The best levels are already past 0.1%.
+        let resiliency = summary().resiliency(0.1).unwrap();
+        assert_eq!(resiliency.asks.volume, 0.);
+        assert_eq!(resiliency.bids.volume, 0.);
+    }
+
+    #[test]
+    fn test_insufficient_depth() {
+        assert_eq!(
+            summary().resiliency(5.),
+            Some(Resiliency {
+                asks: PriceImpact {
+                    volume: 6.,
+                    sufficient_depth: false
+                },
+                bids: PriceImpact {
+                    volume: 7.,
+                    sufficient_depth: false
+                },
+            })
+        );
+
+        let mut one_sided = summary();
+        one_sided.bids.clear();
+        assert_eq!(one_sided.resiliency(1.), None);
+    }
+}
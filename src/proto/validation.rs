@@ -0,0 +1,119 @@
+use super::orderbook;
+use crate::input::Level;
+use parse_display::Display;
+use std::convert::TryFrom;
+
+#[derive(Debug, Display, PartialEq, Clone)]
+/// Invariant broken by an [orderbook::Summary].
+pub enum SummaryValidationError {
+    #[display("Asks are not sorted by ascending price")]
+    UnsortedAsks,
+    #[display("Bids are not sorted by descending price")]
+    UnsortedBids,
+    #[display("Level with a non positive or non finite price or amount")]
+    InvalidLevel,
+    #[display("Spread is {actual} but should be {expected}")]
+    WrongSpread { expected: f64, actual: f64 },
+}
+
+impl orderbook::Summary {
+    /// Checks the invariants of a [orderbook::Summary] as produced by the merge:
+    /// - All prices and amounts are positive and finite.
+    /// - Asks are sorted by ascending price and bids by descending price.
+    /// - The spread is `asks[0].price - bids[0].price`, or 0 if either side is empty.
+    ///
+    /// Transforms which modify the spread, like rounding it, break the last invariant.
+    pub fn is_valid(&self) -> Result<(), SummaryValidationError> {
+        if self
+            .asks
+            .iter()
+            .chain(self.bids.iter())
+            .any(|level| Level::try_from(level).is_err())
+        {
+            return Err(SummaryValidationError::InvalidLevel);
+        }
+        if !self.asks.windows(2).all(|w| w[0].price <= w[1].price) {
+            return Err(SummaryValidationError::UnsortedAsks);
+        }
+        if !self.bids.windows(2).all(|w| w[0].price >= w[1].price) {
+            return Err(SummaryValidationError::UnsortedBids);
+        }
+
+        let expected = match (self.asks.first(), self.bids.first()) {
+            (Some(ask), Some(bid)) => ask.price - bid.price,
+            _ => 0.,
+        };
+        if self.spread != expected {
+            return Err(SummaryValidationError::WrongSpread {
+                expected,
+                actual: self.spread,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    fn summary() -> orderbook::Summary {
+        orderbook::Summary {
+            asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+            bids: vec![lvl1!(1., 1.), lvl0!(0.5, 1.)],
+            spread: 1.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert_eq!(summary().is_valid(), Ok(()));
+        assert_eq!(orderbook::Summary::default().is_valid(), Ok(()));
+
+        let mut one_sided = summary();
+        one_sided.bids.clear();
+        assert!(one_sided.is_valid().is_err());
+        one_sided.spread = 0.;
+        assert_eq!(one_sided.is_valid(), Ok(()));
+
+        let mut unsorted = summary();
+        unsorted.asks.reverse();
+        unsorted.spread = 2.;
+        assert_eq!(
+            unsorted.is_valid(),
+            Err(SummaryValidationError::UnsortedAsks)
+        );
+
+        let mut unsorted = summary();
+        unsorted.bids.reverse();
+        unsorted.spread = 1.5;
+        assert_eq!(
+            unsorted.is_valid(),
+            Err(SummaryValidationError::UnsortedBids)
+        );
+
+        let mut invalid = summary();
+        invalid.bids[1].amount = -1.;
+        assert_eq!(
+            invalid.is_valid(),
+            Err(SummaryValidationError::InvalidLevel)
+        );
+        invalid.bids[1].amount = std::f64::NAN;
+        assert_eq!(
+            invalid.is_valid(),
+            Err(SummaryValidationError::InvalidLevel)
+        );
+
+        let mut wrong_spread = summary();
+        wrong_spread.spread = 0.5;
+        assert_eq!(
+            wrong_spread.is_valid(),
+            Err(SummaryValidationError::WrongSpread {
+                expected: 1.,
+                actual: 0.5
+            })
+        );
+    }
+}
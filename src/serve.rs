@@ -1,22 +1,62 @@
-use crate::proto::orderbook;
+use crate::{
+    input::{Exchange, UnknownExchange},
+    merge::ExchangeFilter,
+    proto::orderbook,
+};
 use async_stream::stream;
 use orderbook::orderbook_aggregator_server::OrderbookAggregator;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::watch::Receiver;
 use tokio_stream::Stream;
 use tonic::{Request, Response, Status};
+#[cfg(feature = "load-test")]
+use {
+    std::time::Duration,
+    tokio::{
+        spawn,
+        time::{timeout_at, Instant},
+    },
+};
 
 #[derive(Clone)]
 /// [OrderbookAggregator] server.
-/// Responds to BookSummary requests with a stream of the values in `rx`.
+/// Responds to BookSummary requests with a stream of the values in `rx`, truncated to the requested depth,
+/// and to BookSummaryDiff and BookDiff requests with a stream of the changes since the previous value sent to each client.
+/// BlockExchange requests update the [ExchangeFilter] provided with [with_exchange_filter](Self::with_exchange_filter).
 pub struct Aggregator {
     rx: Receiver<Option<orderbook::Summary>>,
+    validate: bool,
+    filter: Option<Arc<RwLock<ExchangeFilter>>>,
 }
 
 impl Aggregator {
     /// Returns a new [Aggregator] which will respond to rpc requests with a stream of the values in `rx`.
     pub fn new(rx: Receiver<Option<orderbook::Summary>>) -> Self {
-        Self { rx }
+        Self {
+            rx,
+            validate: false,
+            filter: None,
+        }
+    }
+
+    /// If `validate` is true, every summary is checked with [orderbook::Summary::is_valid] before
+    /// being sent, invalid summaries are logged and skipped.
+    ///
+    /// Meant as a debug mode, since it adds a pass over every summary for every client.
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Updates `filter` on BlockExchange requests, it should be the filter of the [MergeState](crate::merge::MergeState)
+    /// which produces the summaries in `rx`, see [MergeState::with_exchange_filter](crate::merge::MergeState::with_exchange_filter).
+    ///
+    /// Without a filter BlockExchange requests fail with [tonic::Code::Unimplemented].
+    pub fn with_exchange_filter(mut self, filter: Arc<RwLock<ExchangeFilter>>) -> Self {
+        self.filter = Some(filter);
+        self
     }
 }
 
@@ -26,17 +66,483 @@ impl OrderbookAggregator for Aggregator {
         Pin<Box<dyn Stream<Item = Result<orderbook::Summary, Status>> + Send + Sync>>;
     async fn book_summary(
         &self,
-        _: Request<orderbook::Empty>,
+        request: Request<orderbook::BookSummaryRequest>,
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
         let mut rx = self.rx.clone();
+        let validate = self.validate;
+        let depth = request.into_inner().depth as usize;
+
+        Ok(Response::new(Box::pin(stream! {
+            while let Ok(_) = rx.changed().await{
+                let cloned = rx.borrow().clone();
+                if let Some(summary) = cloned{
+                    if is_servable(validate, &summary) {
+                        yield Ok(with_depth(summary, depth))
+                    }
+                }
+            }
+        })))
+    }
+
+    type BookSummaryDiffStream =
+        Pin<Box<dyn Stream<Item = Result<orderbook::SummaryDiff, Status>> + Send + Sync>>;
+    async fn book_summary_diff(
+        &self,
+        _: Request<orderbook::Empty>,
+    ) -> Result<Response<Self::BookSummaryDiffStream>, Status> {
+        let mut rx = self.rx.clone();
+        let validate = self.validate;
+
+        Ok(Response::new(Box::pin(stream! {
+            // Last summary sent to this client.
+            let mut last: Option<orderbook::Summary> = None;
+            while let Ok(_) = rx.changed().await{
+                let cloned = rx.borrow().clone();
+                if let Some(summary) = cloned{
+                    if is_servable(validate, &summary) {
+                        let diff = match &last {
+                            Some(last) => orderbook::SummaryDiff::between(last, &summary),
+                            None => orderbook::SummaryDiff::snapshot(&summary),
+                        };
+                        last = Some(summary);
+                        yield Ok(diff)
+                    }
+                }
+            }
+        })))
+    }
+
+    type BookDiffStream =
+        Pin<Box<dyn Stream<Item = Result<orderbook::OrderbookDiff, Status>> + Send + Sync>>;
+    async fn book_diff(
+        &self,
+        _: Request<orderbook::Empty>,
+    ) -> Result<Response<Self::BookDiffStream>, Status> {
+        let mut rx = self.rx.clone();
+        let validate = self.validate;
 
         Ok(Response::new(Box::pin(stream! {
+            // Last summary sent to this client, the first diff adds every level.
+            let mut last = orderbook::Summary::default();
             while let Ok(_) = rx.changed().await{
                 let cloned = rx.borrow().clone();
                 if let Some(summary) = cloned{
-                    yield Ok(summary)
+                    if is_servable(validate, &summary) {
+                        let diff = orderbook::OrderbookDiff::between(&last, &summary);
+                        last = summary;
+                        yield Ok(diff)
+                    }
                 }
             }
         })))
     }
+
+    async fn get_spread(
+        &self,
+        _: Request<orderbook::Empty>,
+    ) -> Result<Response<orderbook::SpreadResponse>, Status> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is before the unix epoch")
+            .as_millis() as u64;
+
+        // The borrow is dropped at the end of the statement, it must not be held for long
+        // since it blocks the merge task from publishing new summaries.
+        let response = self
+            .rx
+            .borrow()
+            .as_ref()
+            .map(|summary| spread_response(summary, timestamp));
+
+        response
+            .map(Response::new)
+            .ok_or_else(|| Status::unavailable("No orderbook data received yet"))
+    }
+
+    async fn block_exchange(
+        &self,
+        request: Request<orderbook::BlockExchangeRequest>,
+    ) -> Result<Response<orderbook::Empty>, Status> {
+        let filter = self
+            .filter
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("The server has no exchange filter"))?;
+        let request = request.into_inner();
+        let exchange: Exchange = request
+            .exchange
+            .parse()
+            .map_err(|err: UnknownExchange| Status::invalid_argument(err.to_string()))?;
+
+        filter
+            .write()
+            .expect("ExchangeFilter lock poisoned")
+            .set_excluded(exchange, request.blocked);
+        eprintln!(
+            "{} {} through BlockExchange",
+            if request.blocked {
+                "Blocked"
+            } else {
+                "Unblocked"
+            },
+            exchange
+        );
+        Ok(Response::new(orderbook::Empty {}))
+    }
+}
+
+/// Returns the view of `summary` for a client which requested `depth` levels per side, 0 keeps every level.
+///
+/// Only the levels are truncated, the rest of the fields still describe the whole merged book.
+fn with_depth(mut summary: orderbook::Summary, depth: usize) -> orderbook::Summary {
+    if depth > 0 {
+        summary.asks.truncate(depth);
+        summary.bids.truncate(depth);
+    }
+    summary
+}
+
+/// Returns false if `validate` is true and `summary` is not valid, in which case it's logged.
+fn is_servable(validate: bool, summary: &orderbook::Summary) -> bool {
+    if validate {
+        if let Err(err) = summary.is_valid() {
+            eprintln!("Invalid summary: {}, skipping {:?}", err, summary);
+            return false;
+        }
+    }
+    true
+}
+
+/// Returns a new [orderbook::SpreadResponse] with the top of book of `summary`.
+///
+/// `best_bid`, `best_ask` and `mid` are 0 if the corresponding side of `summary` is empty.
+fn spread_response(summary: &orderbook::Summary, timestamp: u64) -> orderbook::SpreadResponse {
+    let best_bid = summary.bids.first().map(|level| level.price);
+    let best_ask = summary.asks.first().map(|level| level.price);
+
+    let mid = match (best_bid, best_ask) {
+        (Some(bid), Some(ask)) => (bid + ask) / 2.,
+        _ => 0.,
+    };
+
+    orderbook::SpreadResponse {
+        spread: summary.spread,
+        mid,
+        best_bid: best_bid.unwrap_or(0.),
+        best_ask: best_ask.unwrap_or(0.),
+        timestamp,
+    }
+}
+
+#[cfg(feature = "load-test")]
+#[derive(Debug, Clone, PartialEq)]
+/// Results of a [load_test] run.
+pub struct LoadTestResult {
+    /// Number of summaries received by each client.
+    pub summaries_per_client: Vec<u64>,
+    /// Number of clients which failed to connect or whose stream ended before the end of the test.
+    pub error_count: u64,
+    /// 99th percentile of the time between consecutive summaries received by a client.
+    ///
+    /// Summaries don't carry a timestamp, so the time between them is used as the latency,
+    /// a slow server delays every client's next summary.
+    pub p99_latency_ms: f64,
+}
+
+#[cfg(feature = "load-test")]
+/// Connects `num_clients` concurrent clients to the server at `addr`, for example `http://0.0.0.0:5005`,
+/// streams [BookSummary](OrderbookAggregator::book_summary) for `duration` and reports how the server coped.
+pub async fn load_test(addr: &str, num_clients: usize, duration: Duration) -> LoadTestResult {
+    let deadline = Instant::now() + duration;
+    let clients = (0..num_clients)
+        .map(|_| spawn(load_test_client(addr.to_string(), deadline)))
+        .collect::<Vec<_>>();
+
+    let mut result = LoadTestResult {
+        summaries_per_client: Vec::with_capacity(num_clients),
+        error_count: 0,
+        p99_latency_ms: 0.,
+    };
+    let mut intervals_ms = Vec::new();
+    for client in clients {
+        match client.await {
+            Ok(stats) => {
+                result.summaries_per_client.push(stats.summaries);
+                result.error_count += stats.error as u64;
+                intervals_ms.extend(stats.intervals_ms);
+            }
+            Err(err) => {
+                eprintln!("Load test client panicked: {}", err);
+                result.summaries_per_client.push(0);
+                result.error_count += 1;
+            }
+        }
+    }
+    result.p99_latency_ms = p99(intervals_ms);
+    result
+}
+
+#[cfg(feature = "load-test")]
+#[derive(Default)]
+/// What a single [load_test] client observed.
+struct ClientStats {
+    summaries: u64,
+    intervals_ms: Vec<f64>,
+    error: bool,
+}
+
+#[cfg(feature = "load-test")]
+/// Streams summaries from `addr` until `deadline`.
+async fn load_test_client(addr: String, deadline: Instant) -> ClientStats {
+    use orderbook::orderbook_aggregator_client::OrderbookAggregatorClient;
+
+    let mut stats = ClientStats::default();
+    let mut client = match OrderbookAggregatorClient::connect(addr).await {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Load test client could not connect: {}", err);
+            stats.error = true;
+            return stats;
+        }
+    };
+    let mut response = match client
+        .book_summary(Request::new(orderbook::BookSummaryRequest::default()))
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            eprintln!("Load test client request failed: {}", status);
+            stats.error = true;
+            return stats;
+        }
+    };
+
+    let mut last = None;
+    loop {
+        match timeout_at(deadline, response.message()).await {
+            Ok(Ok(Some(_))) => {
+                let now = Instant::now();
+                if let Some(last) = last {
+                    stats.intervals_ms.push((now - last).as_secs_f64() * 1000.);
+                }
+                last = Some(now);
+                stats.summaries += 1;
+            }
+            Ok(Ok(None)) => {
+                eprintln!("Load test client stream ended early");
+                stats.error = true;
+                break;
+            }
+            Ok(Err(status)) => {
+                eprintln!("Load test client stream failed: {}", status);
+                stats.error = true;
+                break;
+            }
+            // The test is over.
+            Err(_) => break,
+        }
+    }
+    stats
+}
+
+#[cfg(feature = "load-test")]
+/// Returns the 99th percentile of `samples` using the nearest rank method, 0 if `samples` is empty.
+fn p99(mut samples: Vec<f64>) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    samples.sort_by(|a, b| a.partial_cmp(b).expect("Intervals are never NaN"));
+    let rank = (samples.len() as f64 * 0.99).ceil() as usize;
+    samples[rank - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+    use tokio::sync::watch;
+    use tokio_stream::StreamExt;
+
+    fn summary(ask: f64, bid: f64) -> orderbook::Summary {
+        orderbook::Summary {
+            asks: vec![lvl0!(ask, 1.), lvl1!(ask + 1., 1.)],
+            bids: vec![lvl1!(bid, 1.), lvl0!(bid - 0.5, 1.)],
+            spread: ask - bid,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book_summary_diff() {
+        let (tx, rx) = watch::channel(None);
+        let aggregator = Aggregator::new(rx);
+        let connect = || async {
+            aggregator
+                .book_summary_diff(Request::new(orderbook::Empty {}))
+                .await
+                .unwrap()
+                .into_inner()
+        };
+
+        tx.send(Some(summary(2., 1.))).unwrap();
+        let mut first = connect().await;
+        let mut first_book = orderbook::Summary::default();
+        let diff = first.next().await.unwrap().unwrap();
+        assert!(diff.snapshot);
+        diff.apply(&mut first_book);
+        assert_eq!(first_book, summary(2., 1.));
+
+        tx.send(Some(summary(3., 1.))).unwrap();
+        let mut second = connect().await;
+        let mut second_book = orderbook::Summary::default();
+        let diff = second.next().await.unwrap().unwrap();
+        assert!(diff.snapshot);
+        diff.apply(&mut second_book);
+        assert_eq!(second_book, summary(3., 1.));
+
+        // The first client gets the changes since its snapshot.
+        let diff = first.next().await.unwrap().unwrap();
+        assert!(!diff.snapshot);
+        assert_eq!(
+            diff.asks,
+            vec![lvl0!(3., 1.), lvl1!(4., 1.), lvl0!(2., 0.), lvl1!(3., 0.)]
+        );
+        diff.apply(&mut first_book);
+        assert_eq!(first_book, summary(3., 1.));
+
+        tx.send(Some(summary(3., 2.))).unwrap();
+        for (stream, book) in vec![
+            (&mut first, &mut first_book),
+            (&mut second, &mut second_book),
+        ] {
+            let diff = stream.next().await.unwrap().unwrap();
+            assert!(!diff.snapshot);
+            assert!(diff.asks.is_empty());
+            diff.apply(book);
+            assert_eq!(*book, summary(3., 2.));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_exchange() {
+        let (_tx, rx) = watch::channel(None);
+        let request = |exchange: &str, blocked| {
+            Request::new(orderbook::BlockExchangeRequest {
+                exchange: exchange.to_string(),
+                blocked,
+            })
+        };
+        let status = Aggregator::new(rx.clone())
+            .block_exchange(request("binance", true))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+
+        let filter = Arc::new(RwLock::new(ExchangeFilter::default()));
+        let aggregator = Aggregator::new(rx).with_exchange_filter(filter.clone());
+        aggregator
+            .block_exchange(request("Binance", true))
+            .await
+            .unwrap();
+        assert!(filter.read().unwrap().is_excluded(Exchange::Binance));
+        aggregator
+            .block_exchange(request("binance", false))
+            .await
+            .unwrap();
+        assert!(!filter.read().unwrap().is_excluded(Exchange::Binance));
+
+        let status = aggregator
+            .block_exchange(request("ftx", true))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_book_diff() {
+        let (tx, rx) = watch::channel(None);
+        let aggregator = Aggregator::new(rx);
+        let mut diffs = aggregator
+            .book_diff(Request::new(orderbook::Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        tx.send(Some(summary(2., 1.))).unwrap();
+        let diff = diffs.next().await.unwrap().unwrap();
+        assert_eq!(diff.asks.unwrap().added, summary(2., 1.).asks);
+        assert_eq!(diff.bids.unwrap().added, summary(2., 1.).bids);
+
+        tx.send(Some(summary(3., 1.))).unwrap();
+        let diff = diffs.next().await.unwrap().unwrap();
+        assert_eq!(
+            diff.asks.unwrap(),
+            orderbook::LevelChanges {
+                added: vec![lvl0!(3., 1.), lvl1!(4., 1.)],
+                removed: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+                changed: vec![],
+            }
+        );
+        assert_eq!(diff.bids.unwrap(), orderbook::LevelChanges::default());
+    }
+
+    #[test]
+    fn test_with_depth() {
+        assert_eq!(with_depth(summary(2., 1.), 0), summary(2., 1.));
+        assert_eq!(with_depth(summary(2., 1.), 5), summary(2., 1.));
+
+        let truncated = with_depth(summary(2., 1.), 1);
+        assert_eq!(truncated.asks, vec![lvl0!(2., 1.)]);
+        assert_eq!(truncated.bids, vec![lvl1!(1., 1.)]);
+        assert_eq!(truncated.spread, 1.);
+    }
+
+    #[test]
+    fn test_spread_response() {
+        assert_eq!(
+            spread_response(
+                &orderbook::Summary {
+                    asks: vec![lvl0!(2., 1.), lvl1!(3., 1.)],
+                    bids: vec![lvl1!(1., 1.), lvl0!(0.5, 1.)],
+                    spread: 1.,
+                    ..Default::default()
+                },
+                42
+            ),
+            orderbook::SpreadResponse {
+                spread: 1.,
+                mid: 1.5,
+                best_bid: 1.,
+                best_ask: 2.,
+                timestamp: 42
+            }
+        );
+
+        assert_eq!(
+            spread_response(
+                &orderbook::Summary {
+                    asks: vec![lvl0!(2., 1.)],
+                    bids: vec![],
+                    spread: 0.,
+                    ..Default::default()
+                },
+                42
+            ),
+            orderbook::SpreadResponse {
+                spread: 0.,
+                mid: 0.,
+                best_bid: 0.,
+                best_ask: 2.,
+                timestamp: 42
+            }
+        );
+    }
+
+    #[cfg(feature = "load-test")]
+    #[test]
+    fn test_p99() {
+        assert_eq!(p99(vec![]), 0.);
+        assert_eq!(p99(vec![5.]), 5.);
+        assert_eq!(p99((1..=100).rev().map(f64::from).collect()), 99.);
+        assert_eq!(p99((1..=1000).map(f64::from).collect()), 990.);
+    }
 }
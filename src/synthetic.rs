@@ -0,0 +1,223 @@
+use crate::{
+    input::{InputUpdate, Level},
+    TOP_LEVELS,
+};
+use arrayvec::ArrayVec;
+use async_stream::stream;
+use std::convert::TryInto;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use tokio_stream::Stream;
+
+/// Default maximum relative change of prices and amounts per update, see [SyntheticMarket::with_volatility].
+pub const DEFAULT_VOLATILITY: f64 = 0.001;
+
+/// Generates plausible [InputUpdates](InputUpdate) by perturbing a base book per [Exchange](crate::input::Exchange),
+/// for benchmarks and soak tests.
+///
+/// Every update moves all the prices of the book by the same random factor, so the book stays sorted
+/// and uncrossed, and changes the amount of each level independently.
+pub struct SyntheticMarket {
+    exchanges: Vec<SyntheticExchange>,
+    volatility: f64,
+    rng: XorShift,
+}
+
+/// Latest book and schedule of an exchange in a [SyntheticMarket].
+struct SyntheticExchange {
+    book: InputUpdate,
+    interval: Duration,
+    /// Time since the start of the market at which the next update is due.
+    next: Duration,
+}
+
+impl SyntheticMarket {
+    /// Returns a new [SyntheticMarket] with no exchanges, the same `seed` always generates the same updates.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            exchanges: Vec::new(),
+            volatility: DEFAULT_VOLATILITY,
+            rng: XorShift::new(seed),
+        }
+    }
+
+    /// Sets the maximum relative change of prices and amounts per update.
+    ///
+    /// Panics if `volatility` is not between 0 and 1.
+    pub fn with_volatility(mut self, volatility: f64) -> Self {
+        assert!(
+            (0. ..1.).contains(&volatility),
+            "SyntheticMarket volatility must be between 0 and 1"
+        );
+        self.volatility = volatility;
+        self
+    }
+
+    /// Adds an exchange which starts from `base` and produces `rate` updates per second.
+    ///
+    /// Panics if `rate` is not positive and finite.
+    pub fn with_exchange(mut self, base: InputUpdate, rate: f64) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.,
+            "SyntheticMarket rate must be positive and finite"
+        );
+        let interval = Duration::from_secs_f64(1. / rate);
+        self.exchanges.push(SyntheticExchange {
+            book: base,
+            interval,
+            next: interval,
+        });
+        self
+    }
+
+    /// Returns the next update and the time since the start of the market at which it's due,
+    /// [None] if there are no exchanges.
+    pub fn next_update(&mut self) -> Option<(Duration, InputUpdate)> {
+        let volatility = self.volatility;
+        let rng = &mut self.rng;
+        let exchange = self.exchanges.iter_mut().min_by_key(|e| e.next)?;
+
+        let due = exchange.next;
+        exchange.next += exchange.interval;
+        exchange.book = perturb(exchange.book.clone(), volatility, rng);
+        Some((due, exchange.book.clone()))
+    }
+
+    /// Returns a [Stream] which yields every update when it's due.
+    pub fn into_stream(mut self) -> impl Stream<Item = InputUpdate> {
+        stream! {
+            let start = Instant::now();
+            while let Some((due, update)) = self.next_update() {
+                sleep_until(start + due).await;
+                yield update;
+            }
+        }
+    }
+}
+
+/// Returns `book` with the prices moved by a common random factor and each amount changed, by at most `volatility`.
+fn perturb(book: InputUpdate, volatility: f64, rng: &mut XorShift) -> InputUpdate {
+    let (exchange, asks, bids) = book.take();
+    let factor = 1. + volatility * rng.next_signed();
+    let mut perturb_side = |levels: &[Level]| -> ArrayVec<[Level; TOP_LEVELS]> {
+        levels
+            .iter()
+            .map(|level| {
+                // Scaling every price by the same positive factor keeps them sorted.
+                let level = level.with_price_factor(factor).unwrap_or(*level);
+                let amount =
+                    Into::<f64>::into(level.amount) * (1. + volatility * rng.next_signed());
+                Level {
+                    amount: amount.try_into().unwrap_or(level.amount),
+                    ..level
+                }
+            })
+            .collect()
+    };
+    let asks = perturb_side(&asks);
+    let bids = perturb_side(&bids);
+    InputUpdate::new(exchange, asks, bids)
+}
+
+/// Minimal [xorshift64*](https://en.wikipedia.org/wiki/Xorshift#xorshift*) generator,
+/// good enough for synthetic data without adding a dependency.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Returns a new [XorShift], the state can't be 0 so a `seed` of 0 is replaced.
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a uniformly distributed number between -1 and 1.
+    fn next_signed(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa of an f64 between 0 and 1.
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * 2. - 1.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{arrayvec, input::Exchange, is_sorted_strict};
+    use tokio_stream::StreamExt;
+
+    fn market() -> SyntheticMarket {
+        SyntheticMarket::new(42)
+            .with_volatility(0.01)
+            .with_exchange(
+                InputUpdate::new(
+                    Exchange::Binance,
+                    arrayvec![lvl!(2., 1.), lvl!(2.1, 2.), lvl!(2.2, 3.)],
+                    arrayvec![lvl!(1.9, 1.), lvl!(1.8, 2.), lvl!(1.7, 3.)],
+                ),
+                10.,
+            )
+            .with_exchange(
+                InputUpdate::new(
+                    Exchange::Bitstamp,
+                    arrayvec![lvl!(2.05, 1.), lvl!(2.15, 1.)],
+                    arrayvec![lvl!(1.95, 1.)],
+                ),
+                20.,
+            )
+    }
+
+    #[test]
+    fn test_sorted() {
+        let mut market = market();
+        let mut counts = [0; Exchange::VARIANT_COUNT];
+        let mut last_due = Duration::from_secs(0);
+        for _ in 0..3000 {
+            let (due, update) = market.next_update().unwrap();
+            assert!(due >= last_due);
+            last_due = due;
+
+            let (exchange, asks, bids) = update.take();
+            counts[exchange as usize] += 1;
+            assert!(is_sorted_strict(&asks, Level::cmp_ask));
+            assert!(is_sorted_strict(&bids, Level::cmp_bid));
+            assert!(asks[0].price > bids[0].price);
+        }
+        assert_eq!(counts, [1000, 2000, 0, 0]);
+
+        assert!(SyntheticMarket::new(1).next_update().is_none());
+    }
+
+    #[test]
+    fn test_seed() {
+        let updates = |mut market: SyntheticMarket| {
+            (0..10)
+                .map(|_| market.next_update().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(updates(market()), updates(market()));
+        assert_ne!(updates(market()), updates(market().with_volatility(0.02)));
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        tokio::time::pause();
+        let start = Instant::now();
+        let stream = market().into_stream().take(3);
+        tokio::pin!(stream);
+        let mut exchanges = Vec::new();
+        while let Some(update) = stream.next().await {
+            exchanges.push(update.exchange());
+        }
+        assert_eq!(
+            exchanges,
+            vec![Exchange::Bitstamp, Exchange::Binance, Exchange::Bitstamp]
+        );
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+    }
+}
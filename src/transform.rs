@@ -0,0 +1,281 @@
+use crate::{merge::microprice, proto::orderbook};
+
+/// Post processing step applied to every [orderbook::Summary] between the merge and the server.
+///
+/// Implemented for every `Fn(orderbook::Summary) -> orderbook::Summary` so closures can be used as transforms.
+pub trait SummaryTransform: Send + Sync {
+    /// Returns the transformed `summary`.
+    fn apply(&self, summary: orderbook::Summary) -> orderbook::Summary;
+}
+
+impl<F: Fn(orderbook::Summary) -> orderbook::Summary + Send + Sync> SummaryTransform for F {
+    fn apply(&self, summary: orderbook::Summary) -> orderbook::Summary {
+        self(summary)
+    }
+}
+
+#[derive(Default)]
+/// Ordered list of [SummaryTransforms](SummaryTransform), each one receives the output of the previous one.
+pub struct Pipeline {
+    transforms: Vec<Box<dyn SummaryTransform>>,
+}
+
+impl Pipeline {
+    /// Returns a new empty [Pipeline], which returns summaries unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `transform` to the end of the [Pipeline].
+    pub fn with(mut self, transform: impl SummaryTransform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Returns `summary` after applying every transform in order.
+    pub fn apply(&self, summary: orderbook::Summary) -> orderbook::Summary {
+        self.transforms
+            .iter()
+            .fold(summary, |summary, transform| transform.apply(summary))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Truncates both sides of the book to at most the wrapped number of levels.
+pub struct DepthCap(pub usize);
+
+impl SummaryTransform for DepthCap {
+    fn apply(&self, mut summary: orderbook::Summary) -> orderbook::Summary {
+        summary.asks.truncate(self.0);
+        summary.bids.truncate(self.0);
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Truncates both sides of the book to the levels needed to reach the wrapped cumulative amount.
+///
+/// The level which reaches the target is kept, if a side doesn't have enough volume all its levels are kept.
+pub struct VolumeCap(pub f64);
+
+impl VolumeCap {
+    /// Returns the number of `levels` needed for their cumulative amount to reach the target.
+    fn depth(&self, levels: &[orderbook::Level]) -> usize {
+        let mut volume = 0.;
+        levels
+            .iter()
+            .position(|level| {
+                volume += level.amount;
+                volume >= self.0
+            })
+            .map_or(levels.len(), |i| i + 1)
+    }
+}
+
+impl SummaryTransform for VolumeCap {
+    fn apply(&self, mut summary: orderbook::Summary) -> orderbook::Summary {
+        summary.asks.truncate(self.depth(&summary.asks));
+        summary.bids.truncate(self.depth(&summary.bids));
+        summary
+    }
+}
+
+/// Separator between the exchanges of the levels collapsed by [UniquePrices].
+pub const EXCHANGE_SEPARATOR: &str = "+";
+
+#[derive(Debug, Clone, Copy)]
+/// Collapses consecutive levels with the same price into one, so prices are strictly monotonic on both sides.
+///
+/// The amounts of the collapsed levels are added and their exchanges joined with [EXCHANGE_SEPARATOR]
+/// in ranking order, for example `binance+bitstamp`. The microprice is recalculated with the new top of book amounts.
+pub struct UniquePrices;
+
+impl UniquePrices {
+    /// Returns `levels` with the levels which have the same price as the previous one merged into it.
+    fn collapse(levels: Vec<orderbook::Level>) -> Vec<orderbook::Level> {
+        let mut output: Vec<orderbook::Level> = Vec::with_capacity(levels.len());
+        for level in levels {
+            match output.last_mut() {
+                Some(last) if last.price == level.price => {
+                    last.amount += level.amount;
+                    if !last
+                        .exchange
+                        .split(EXCHANGE_SEPARATOR)
+                        .any(|exchange| exchange == level.exchange)
+                    {
+                        last.exchange.push_str(EXCHANGE_SEPARATOR);
+                        last.exchange.push_str(&level.exchange);
+                    }
+                }
+                _ => output.push(level),
+            }
+        }
+        output
+    }
+}
+
+impl SummaryTransform for UniquePrices {
+    fn apply(&self, mut summary: orderbook::Summary) -> orderbook::Summary {
+        summary.asks = Self::collapse(summary.asks);
+        summary.bids = Self::collapse(summary.bids);
+        summary.microprice = microprice(&summary.asks, &summary.bids);
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Rounds the spread to `decimals` decimal places.
+pub struct RoundSpread {
+    factor: f64,
+}
+
+impl RoundSpread {
+    /// Returns a new [RoundSpread] which rounds to `decimals` decimal places.
+    pub fn new(decimals: i32) -> Self {
+        Self {
+            factor: 10f64.powi(decimals),
+        }
+    }
+}
+
+impl SummaryTransform for RoundSpread {
+    fn apply(&self, mut summary: orderbook::Summary) -> orderbook::Summary {
+        summary.spread = (summary.spread * self.factor).round() / self.factor;
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Exchange;
+
+    fn summary() -> orderbook::Summary {
+        orderbook::Summary {
+            asks: vec![lvl0!(1.26, 1.), lvl1!(1.3, 1.)],
+            bids: vec![lvl1!(1.2, 1.), lvl0!(1.1, 1.)],
+            spread: 0.06,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_depth_cap() {
+        let capped = DepthCap(1).apply(summary());
+        assert_eq!(capped.asks, vec![lvl0!(1.26, 1.)]);
+        assert_eq!(capped.bids, vec![lvl1!(1.2, 1.)]);
+
+        assert_eq!(DepthCap(5).apply(summary()), summary());
+    }
+
+    #[test]
+    fn test_volume_cap() {
+        let summary = orderbook::Summary {
+            asks: vec![lvl0!(1.26, 1.), lvl1!(1.3, 2.), lvl0!(1.4, 4.)],
+            bids: vec![lvl1!(1.2, 0.5), lvl0!(1.1, 0.5), lvl1!(1., 3.)],
+            spread: 0.06,
+            ..Default::default()
+        };
+
+        let capped = VolumeCap(2.).apply(summary.clone());
+        assert_eq!(capped.asks, vec![lvl0!(1.26, 1.), lvl1!(1.3, 2.)]);
+        assert_eq!(
+            capped.bids,
+            vec![lvl1!(1.2, 0.5), lvl0!(1.1, 0.5), lvl1!(1., 3.)]
+        );
+
+        // Reached exactly.
+        let capped = VolumeCap(1.).apply(summary.clone());
+        assert_eq!(capped.asks, vec![lvl0!(1.26, 1.)]);
+        assert_eq!(capped.bids, vec![lvl1!(1.2, 0.5), lvl0!(1.1, 0.5)]);
+
+        // Not enough volume.
+        assert_eq!(VolumeCap(100.).apply(summary.clone()), summary);
+    }
+
+    #[test]
+    fn test_unique_prices() {
+        let book = orderbook::Summary {
+            asks: vec![
+                lvl0!(1.26, 1.),
+                lvl1!(1.26, 0.5),
+                lvl1!(1.3, 1.),
+                lvl0!(1.4, 2.),
+                lvl1!(1.4, 1.),
+            ],
+            bids: vec![lvl1!(1.2, 3.), lvl0!(1.2, 1.), lvl0!(1.1, 1.)],
+            spread: 0.06,
+            ..Default::default()
+        };
+        let unique = UniquePrices.apply(book.clone());
+
+        let collapsed = |exchange: &str, price, amount| orderbook::Level {
+            exchange: exchange.to_string(),
+            price,
+            amount,
+        };
+        assert_eq!(
+            unique.asks,
+            vec![
+                collapsed("binance+bitstamp", 1.26, 1.5),
+                lvl1!(1.3, 1.),
+                collapsed("binance+bitstamp", 1.4, 3.)
+            ]
+        );
+        assert_eq!(
+            unique.bids,
+            vec![collapsed("bitstamp+binance", 1.2, 4.), lvl0!(1.1, 1.)]
+        );
+        assert!(unique.asks.windows(2).all(|w| w[0].price < w[1].price));
+        assert!(unique.bids.windows(2).all(|w| w[0].price > w[1].price));
+        assert_eq!(unique.spread, book.spread);
+        assert_eq!(unique.microprice, microprice(&unique.asks, &unique.bids));
+
+        // Already unique.
+        assert_eq!(UniquePrices.apply(summary()).asks, summary().asks);
+    }
+
+    #[test]
+    fn test_round_spread() {
+        assert_eq!(RoundSpread::new(1).apply(summary()).spread, 0.1);
+        assert_eq!(RoundSpread::new(0).apply(summary()).spread, 0.);
+        assert_eq!(RoundSpread::new(2).apply(summary()).spread, 0.06);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        assert_eq!(Pipeline::new().apply(summary()), summary());
+
+        let times_ten = |mut summary: orderbook::Summary| {
+            summary.spread *= 10.;
+            summary
+        };
+
+        // Rounding after scaling keeps the decimal.
+        assert_eq!(
+            Pipeline::new()
+                .with(times_ten)
+                .with(RoundSpread::new(0))
+                .apply(summary())
+                .spread,
+            1.
+        );
+        // Rounding before scaling loses it.
+        assert_eq!(
+            Pipeline::new()
+                .with(RoundSpread::new(0))
+                .with(times_ten)
+                .apply(summary())
+                .spread,
+            0.
+        );
+
+        let output = Pipeline::new()
+            .with(DepthCap(1))
+            .with(RoundSpread::new(1))
+            .apply(summary());
+        assert_eq!(output.asks.len(), 1);
+        assert_eq!(output.bids.len(), 1);
+        assert_eq!(output.spread, 0.1);
+    }
+}
@@ -0,0 +1,56 @@
+//! Checks the argument parsing of the server binary, the server isn't started.
+use assert_cmd::Command;
+
+/// Returns the server binary with none of the environment variables which provide default flags.
+fn server() -> Command {
+    let mut command = Command::cargo_bin("server").unwrap();
+    for var in &[
+        "PAIR",
+        "PORT",
+        "EXCHANGES",
+        "TOP_LEVELS",
+        "CHANNEL_SIZE",
+        "SPREAD_HISTORY_SECONDS",
+    ] {
+        command.env_remove(var);
+    }
+    command
+}
+
+#[test]
+fn test_help() {
+    let output = server().arg("--help").assert().success();
+    let help = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    for flag in &[
+        "--pair",
+        "--port",
+        "--exchanges",
+        "--top-levels",
+        "--channel-size",
+        "[env: PAIR=]",
+    ] {
+        assert!(help.contains(flag), "{} missing from:\n{}", flag, help);
+    }
+}
+
+#[test]
+fn test_invalid_exchange() {
+    let output = server()
+        .args(&["--pair", "ethbtc", "--exchanges", "binance,ftx"])
+        .assert()
+        .failure();
+    let error = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(
+        error
+            .contains("Unknown exchange: ftx, expected one of binance, bitstamp, kraken, coinbase"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_missing_pair() {
+    let output = server().assert().failure();
+    let error = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(error.contains("--pair"), "{}", error);
+}
@@ -0,0 +1,121 @@
+//! Boots the merge and the gRPC server with scripted sources and checks what a client receives.
+use arrayvec::ArrayVec;
+use async_stream::stream;
+use orderbook_challenge::{
+    input::{Exchange, InputUpdate, Level},
+    merge::{self, MergeState},
+    proto::orderbook::{
+        self, orderbook_aggregator_client::OrderbookAggregatorClient,
+        orderbook_aggregator_server::OrderbookAggregatorServer,
+    },
+    serve::Aggregator,
+    CHANNEL_SIZE, TOP_LEVELS,
+};
+use std::{convert::TryInto, time::Duration};
+use tokio::{
+    net::TcpListener,
+    spawn,
+    sync::{mpsc, watch},
+    time::timeout,
+};
+use tokio_stream::StreamExt;
+use tonic::transport::Server;
+
+/// Maximum time to wait for each message from the server.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn levels(levels: Vec<(f64, f64)>) -> ArrayVec<[Level; TOP_LEVELS]> {
+    levels
+        .into_iter()
+        .map(|(price, amount)| Level {
+            price: price.try_into().unwrap(),
+            amount: amount.try_into().unwrap(),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_book_summary() {
+    // Scripted sources.
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+
+    let (summaries_tx, summaries_rx) = watch::channel(None);
+    spawn(async move {
+        let stream = merge::merge(rx);
+        tokio::pin!(stream);
+        while let Some(item) = stream.next().await {
+            summaries_tx.send(Some(item)).expect("Watch channel broke!");
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = stream! {
+        loop {
+            yield listener.accept().await.map(|(socket, _)| socket);
+        }
+    };
+    spawn(
+        Server::builder()
+            .add_service(OrderbookAggregatorServer::new(
+                Aggregator::new(summaries_rx).with_validation(true),
+            ))
+            .serve_with_incoming(incoming),
+    );
+
+    let mut client = OrderbookAggregatorClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let mut summaries = client
+        .book_summary(tonic::Request::new(orderbook::BookSummaryRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut top_of_book = client
+        .book_summary(tonic::Request::new(orderbook::BookSummaryRequest {
+            depth: 1,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let binance = InputUpdate::new(
+        Exchange::Binance,
+        levels(vec![(2., 1.), (3., 1.)]),
+        levels(vec![(1., 1.), (0.5, 2.)]),
+    );
+    let bitstamp = InputUpdate::new(
+        Exchange::Bitstamp,
+        levels(vec![(1.5, 1.)]),
+        levels(vec![(1.25, 3.)]),
+    );
+    let mut expected = MergeState::new();
+    for update in vec![binance, bitstamp] {
+        expected.update(update.clone());
+        tx.send(update).await.unwrap();
+        let summary = timeout(TIMEOUT, summaries.message())
+            .await
+            .expect("No summary received in time")
+            .unwrap()
+            .expect("Stream ended");
+        assert_eq!(summary, expected.summary());
+
+        let top = timeout(TIMEOUT, top_of_book.message())
+            .await
+            .expect("No summary received in time")
+            .unwrap()
+            .expect("Stream ended");
+        assert_eq!(top.asks, &summary.asks[..1]);
+        assert_eq!(top.bids, &summary.bids[..1]);
+        assert_eq!(top.spread, summary.spread);
+    }
+
+    let spread = client
+        .get_spread(tonic::Request::new(orderbook::Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(spread.best_ask, 1.5);
+    assert_eq!(spread.best_bid, 1.25);
+    assert_eq!(spread.spread, 0.25);
+}